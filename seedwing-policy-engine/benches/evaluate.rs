@@ -0,0 +1,73 @@
+//! Admission-style throughput of the tree evaluator against compiled programs.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use seedwing_policy_engine::lang::ty::PackagePath;
+use seedwing_policy_engine::runtime::sources::Ephemeral;
+use seedwing_policy_engine::runtime::Builder;
+use seedwing_policy_engine::value::Value;
+use serde_json::json;
+
+const POLICY: &str = r#"
+    type name = "web" || "api" || "worker" || "cron"
+    type replicas = $(self >= 1 && self <= 50) else "replicas must be between 1 and 50, not {self}"
+    type port = $(self > 0 && self < 65536)
+    type container = {
+        name: name,
+        image: "registry.example.com/web" || "registry.example.com/api",
+        ports: [port],
+    }
+    pub type deployment = {
+        name: name,
+        namespace: "prod" || "staging",
+        replicas: replicas,
+        containers: [container],
+    }
+"#;
+
+fn requests() -> Vec<serde_json::Value> {
+    (0..64)
+        .map(|i| json!({
+            "name": if i % 2 == 0 { "web" } else { "api" },
+            "namespace": if i % 3 == 0 { "staging" } else { "prod" },
+            "replicas": if i % 16 == 0 { 0 } else { i % 50 + 1 },
+            "containers": [
+                { "name": "web", "image": "registry.example.com/web", "ports": [80, 443] },
+                { "name": "api", "image": "registry.example.com/api", "ports": [8080] },
+            ],
+        }))
+        .collect()
+}
+
+fn admission(c: &mut Criterion) {
+    let src = Ephemeral::new(PackagePath::from_parts(vec!["k8s"]), POLICY.into());
+    let mut builder = Builder::new();
+    builder.build(src.iter()).unwrap();
+    let runtime = builder.link().unwrap();
+    let program = runtime.compile();
+
+    let requests = requests().iter().map(Value::from).collect::<Vec<_>>();
+
+    let mut group = c.benchmark_group("admission");
+    group.throughput(Throughput::Elements(requests.len() as u64));
+
+    group.bench_function("runtime", |b| {
+        b.iter(|| {
+            for request in &requests {
+                runtime.evaluate_blocking("k8s::deployment".into(), request).unwrap();
+            }
+        })
+    });
+
+    group.bench_function("program", |b| {
+        b.iter(|| {
+            for request in &requests {
+                program.evaluate_blocking("k8s::deployment".into(), request).unwrap();
+            }
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, admission);
+criterion_main!(benches);
@@ -15,7 +15,7 @@ pub fn package() -> FunctionPackage {
 pub struct Base64;
 
 impl Function for Base64 {
-    fn call<'v>(&'v self, value: &'v mut Value) -> Pin<Box<dyn Future<Output=Result<Value, ()>> + 'v>> {
+    fn call<'v>(&'v self, value: &'v Value) -> Pin<Box<dyn Future<Output=Result<Value, ()>> + Send + 'v>> {
         Box::pin(
             async move {
                 if let Some(inner) = value.try_get_string() {
@@ -30,4 +30,8 @@ impl Function for Base64 {
             }
         )
     }
+
+    fn is_async(&self) -> bool {
+        false
+    }
 }
\ No newline at end of file
@@ -0,0 +1,290 @@
+//! Memoization of function calls, for functions opting in.
+
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use crate::function::Function;
+use crate::lang::ty::TypeName;
+use crate::value::Value;
+
+/// How the calls to one function are memoized. By default, results are
+/// kept forever and without limit.
+#[derive(Clone, Debug, Default)]
+pub struct Memoize {
+    ttl: Option<Duration>,
+    capacity: Option<usize>,
+}
+
+impl Memoize {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How long a result is reused for.
+    pub fn set_ttl(&mut self, ttl: Duration) {
+        self.ttl.replace(ttl);
+    }
+
+    /// How many results are kept, evicting the oldest first.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity.replace(capacity);
+    }
+}
+
+/// Results of calls to functions, keyed by function name and input value.
+///
+/// Functions must opt in with [`FunctionCache::memoize`], as only those
+/// returning the same output for the same input may be memoized. Only
+/// successful calls are kept.
+#[derive(Debug, Default)]
+pub struct FunctionCache {
+    functions: HashMap<TypeName, Mutex<Entries>>,
+}
+
+#[derive(Debug)]
+struct Entries {
+    memoize: Memoize,
+    results: HashMap<String, (Instant, Value)>,
+    // keys in the order they were stored
+    order: VecDeque<String>,
+    stats: CacheStats,
+}
+
+impl FunctionCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Memoize calls to the function `name`, such as `sigstore::SHA256`.
+    pub fn memoize(&mut self, name: String, memoize: Memoize) {
+        self.functions.insert(TypeName::from(name), Mutex::new(Entries {
+            memoize,
+            results: Default::default(),
+            order: Default::default(),
+            stats: Default::default(),
+        }));
+    }
+
+    /// Hits and misses of the function `name`, if memoized.
+    pub fn stats(&self, name: String) -> Option<CacheStats> {
+        self.functions.get(&TypeName::from(name)).map(|entries| {
+            let entries = entries.lock().unwrap();
+            CacheStats {
+                entries: entries.results.len(),
+                ..entries.stats.clone()
+            }
+        })
+    }
+
+    /// Forget every result, keeping the stats.
+    pub fn clear(&self) {
+        for entries in self.functions.values() {
+            let mut entries = entries.lock().unwrap();
+            entries.results.clear();
+            entries.order.clear();
+        }
+    }
+
+    /// Wrap `func` to go through the cache, if memoized.
+    pub(crate) fn wrap(self: &Arc<Self>, name: &TypeName, func: Arc<dyn Function>) -> Arc<dyn Function> {
+        if self.functions.contains_key(name) {
+            Arc::new(Memoized {
+                name: name.clone(),
+                func,
+                cache: self.clone(),
+            })
+        } else {
+            func
+        }
+    }
+
+    fn get(&self, name: &TypeName, key: &str) -> Option<Value> {
+        let mut entries = self.functions.get(name)?.lock().unwrap();
+        let ttl = entries.memoize.ttl;
+        match entries.results.get(key) {
+            Some((stored, _)) if ttl.is_some_and(|ttl| stored.elapsed() >= ttl) => {
+                entries.results.remove(key);
+                entries.order.retain(|e| e != key);
+                entries.stats.expirations += 1;
+                entries.stats.misses += 1;
+                None
+            }
+            Some((_, value)) => {
+                let value = value.clone();
+                entries.stats.hits += 1;
+                Some(value)
+            }
+            None => {
+                entries.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn insert(&self, name: &TypeName, key: String, value: Value) {
+        let Some(entries) = self.functions.get(name) else {
+            return;
+        };
+        let mut entries = entries.lock().unwrap();
+        if entries.results.insert(key.clone(), (Instant::now(), value)).is_none() {
+            entries.order.push_back(key);
+        }
+        while entries.memoize.capacity.is_some_and(|capacity| entries.results.len() > capacity) {
+            let Some(oldest) = entries.order.pop_front() else {
+                break;
+            };
+            entries.results.remove(&oldest);
+            entries.stats.evictions += 1;
+        }
+    }
+}
+
+/// Counts for one memoized function.
+#[derive(Clone, Debug, Default)]
+pub struct CacheStats {
+    hits: u64,
+    misses: u64,
+    expirations: u64,
+    evictions: u64,
+    entries: usize,
+}
+
+impl CacheStats {
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    /// Calls not answered from the cache, including those whose result
+    /// had expired.
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    pub fn expirations(&self) -> u64 {
+        self.expirations
+    }
+
+    /// Results dropped to stay within capacity.
+    pub fn evictions(&self) -> u64 {
+        self.evictions
+    }
+
+    /// Results currently kept.
+    pub fn entries(&self) -> usize {
+        self.entries
+    }
+}
+
+#[derive(Debug)]
+struct Memoized {
+    name: TypeName,
+    func: Arc<dyn Function>,
+    cache: Arc<FunctionCache>,
+}
+
+impl Function for Memoized {
+    fn call<'v>(&'v self, input: &'v Value) -> Pin<Box<dyn Future<Output=Result<Value, ()>> + Send + 'v>> {
+        Box::pin(async move {
+            let key = input.canonical();
+            if let Some(output) = self.cache.get(&self.name, &key) {
+                return Ok(output);
+            }
+            let output = self.func.call(input).await;
+            if let Ok(output) = &output {
+                self.cache.insert(&self.name, key, output.clone());
+            }
+            output
+        })
+    }
+
+    fn is_async(&self) -> bool {
+        self.func.is_async()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::future::{ready, Future};
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use serde_json::json;
+    use crate::function::{Counting, FunctionPackage};
+    use crate::lang::ty::PackagePath;
+    use crate::runtime::sources::Ephemeral;
+    use crate::runtime::{Builder, Runtime};
+    use crate::value::Value;
+    use super::{FunctionCache, Memoize};
+
+    fn link(cache: Arc<FunctionCache>, pure: Arc<AtomicUsize>, impure: Arc<AtomicUsize>) -> Arc<Runtime> {
+        let src = Ephemeral::new(PackagePath::from_parts(vec!["foo", "bar"]), r#"
+            pub type pure = test::Pure()
+            pub type impure = test::Impure()
+        "#.into());
+
+        let mut test = FunctionPackage::new();
+        test.register("Pure".into(), Counting(pure));
+        test.register("Impure".into(), Counting(impure));
+
+        let mut builder = Builder::new();
+        builder.add_function_package(PackagePath::from_parts(vec!["test"]), test);
+        builder.set_function_cache(cache);
+        builder.build(src.iter()).unwrap();
+        builder.link().unwrap()
+    }
+
+    #[test]
+    fn memoize_opted_in_functions() {
+        let mut memoize = Memoize::new();
+        memoize.set_capacity(2);
+        let mut cache = FunctionCache::new();
+        cache.memoize("test::Pure".into(), memoize);
+        let cache = Arc::new(cache);
+
+        let pure = Arc::new(AtomicUsize::new(0));
+        let impure = Arc::new(AtomicUsize::new(0));
+        let runtime = link(cache.clone(), pure.clone(), impure.clone());
+
+        for input in [json!({ "a": 1, "b": 2 }), json!({ "b": 2, "a": 1 }), json!(1), json!(1.0)] {
+            let input = (&input).into();
+            assert!(runtime.evaluate_blocking("foo::bar::pure".into(), &input).unwrap().matches());
+            assert!(runtime.evaluate_blocking("foo::bar::impure".into(), &input).unwrap().matches());
+        }
+        assert_eq!(pure.load(Ordering::SeqCst), 3);
+        assert_eq!(impure.load(Ordering::SeqCst), 4);
+
+        let stats = cache.stats("test::Pure".into()).unwrap();
+        assert_eq!((stats.hits(), stats.misses(), stats.evictions(), stats.entries()), (1, 3, 1, 2));
+        assert!(cache.stats("test::Impure".into()).is_none());
+
+        // evicted as the oldest
+        let input = (&json!({ "a": 1, "b": 2 })).into();
+        runtime.evaluate_blocking("foo::bar::pure".into(), &input).unwrap();
+        assert_eq!(pure.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn expire_memoized_results() {
+        let mut memoize = Memoize::new();
+        memoize.set_ttl(Duration::from_millis(20));
+        let mut cache = FunctionCache::new();
+        cache.memoize("test::Pure".into(), memoize);
+        let cache = Arc::new(cache);
+
+        let pure = Arc::new(AtomicUsize::new(0));
+        let runtime = link(cache.clone(), pure.clone(), Default::default());
+
+        let input = (&json!("sha256:abc")).into();
+        runtime.evaluate_blocking("foo::bar::pure".into(), &input).unwrap();
+        runtime.evaluate_blocking("foo::bar::pure".into(), &input).unwrap();
+        assert_eq!(pure.load(Ordering::SeqCst), 1);
+
+        std::thread::sleep(Duration::from_millis(30));
+        runtime.evaluate_blocking("foo::bar::pure".into(), &input).unwrap();
+        assert_eq!(pure.load(Ordering::SeqCst), 2);
+        assert_eq!(cache.stats("test::Pure".into()).unwrap().expirations(), 1);
+    }
+}
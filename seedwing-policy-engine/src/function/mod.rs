@@ -8,9 +8,16 @@ use crate::value::Value;
 
 pub mod sigstore;
 pub mod base64;
+pub mod memo;
 
 pub trait Function : Sync + Send + Debug {
-    fn call<'v>(&'v self, input: &'v mut Value) -> Pin<Box<dyn Future<Output=Result<Value, ()>> + 'v >>;
+    fn call<'v>(&'v self, input: &'v Value) -> Pin<Box<dyn Future<Output=Result<Value, ()>> + Send + 'v>>;
+
+    /// Whether `call` may wait on I/O. Only types reaching no async
+    /// functions can be evaluated without an executor.
+    fn is_async(&self) -> bool {
+        true
+    }
 }
 
 pub struct FunctionPackage {
@@ -40,4 +47,21 @@ impl FunctionPackage {
             })
             .collect()
     }
-}
\ No newline at end of file
+}
+
+/// Counts its calls, matching anything.
+#[cfg(test)]
+#[derive(Debug)]
+pub(crate) struct Counting(pub(crate) Arc<std::sync::atomic::AtomicUsize>);
+
+#[cfg(test)]
+impl Function for Counting {
+    fn call<'v>(&'v self, input: &'v Value) -> Pin<Box<dyn Future<Output=Result<Value, ()>> + Send + 'v>> {
+        self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        Box::pin(std::future::ready(Ok(input.clone())))
+    }
+
+    fn is_async(&self) -> bool {
+        false
+    }
+}
@@ -17,7 +17,7 @@ pub fn package() -> FunctionPackage {
 pub struct Sha256;
 
 impl Function for Sha256 {
-    fn call<'v>(&'v self, input: &'v mut Value) -> Pin<Box<dyn Future<Output=Result<Value, ()>> + 'v>> {
+    fn call<'v>(&'v self, input: &'v Value) -> Pin<Box<dyn Future<Output=Result<Value, ()>> + Send + 'v>> {
         Box::pin(
             async move {
                 if let Some(digest) = input.try_get_string() {
@@ -38,9 +38,8 @@ impl Function for Sha256 {
                                 if let Ok(body) = body {
                                     let body: Result<serde_json::Value, _> = serde_json::from_slice(&*body);
                                     if let Ok(body) = body {
-                                        println!("{:?}", body);
                                         let value = (&body).into();
-                                        println!("OKAY OKAY OKAY");
+                                        tracing::trace!(uuid = %uuid, "found log entry");
                                         //return Ok(value);
                                         transform.push(value)
                                     }
@@ -0,0 +1,110 @@
+use crate::lang::{Located, ParserError, ParserInput};
+use crate::lang::lexer::{name, punct, Token};
+use chumsky::prelude::*;
+use chumsky::Parser;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Attribute {
+    name: Located<String>,
+    args: Vec<Located<String>>,
+}
+
+impl Attribute {
+    pub fn new(name: Located<String>, args: Vec<Located<String>>) -> Self {
+        Self {
+            name,
+            args,
+        }
+    }
+
+    pub fn name(&self) -> Located<String> {
+        self.name.clone()
+    }
+
+    pub fn args(&self) -> &Vec<Located<String>> {
+        &self.args
+    }
+
+    /// The first argument, if any, such as the message of `#[deprecated("use v2")]`.
+    pub fn arg(&self) -> Option<String> {
+        self.args.first().map(|e| e.clone().into_inner())
+    }
+}
+
+pub(crate) fn find_attribute<'a>(attributes: &'a [Located<Attribute>], name: &str) -> Option<&'a Located<Attribute>> {
+    attributes.iter().find(|e| *e.name == name)
+}
+
+#[allow(clippy::result_large_err)]
+pub fn attribute_arg() -> impl Parser<ParserInput, Located<String>, Error=ParserError> + Clone {
+    select! {
+        Token::Str(value) => value,
+        Token::Ident(value) => value,
+        Token::Integer(value) => value,
+        Token::Decimal(value) => value,
+        Token::Keyword(keyword) => keyword.to_string(),
+    }
+        .map_with_span(|v, span| {
+            Located::new(v, span)
+        })
+}
+
+pub fn attribute() -> impl Parser<ParserInput, Located<Attribute>, Error=ParserError> + Clone {
+    punct('#')
+        .ignore_then(
+            name()
+                .then(
+                    attribute_arg()
+                        .separated_by(punct(','))
+                        .allow_trailing()
+                        .delimited_by(punct('('), punct(')'))
+                        .or_not()
+                )
+                .delimited_by(punct('['), punct(']'))
+        )
+        .map_with_span(|(name, args), span| {
+            Located::new(
+                Attribute::new(name, args.unwrap_or_default()),
+                span,
+            )
+        })
+}
+
+pub fn attributes() -> impl Parser<ParserInput, Vec<Located<Attribute>>, Error=ParserError> + Clone {
+    attribute().repeated()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::lang::lexer::tokens;
+
+    #[test]
+    fn parse_attribute_without_args() {
+        let attr = attribute().parse(tokens("#[experimental]")).unwrap().into_inner();
+
+        assert_eq!(&*attr.name(), "experimental");
+        assert!(attr.args().is_empty());
+    }
+
+    #[test]
+    fn parse_keyword_attribute_name() {
+        let attr = attribute().parse(tokens("#[type(object)]")).unwrap().into_inner();
+        assert_eq!(&*attr.name(), "type");
+        assert_eq!(attr.arg(), Some("object".into()));
+    }
+
+    #[test]
+    fn parse_attribute_args() {
+        let attr = attribute().parse(tokens(r#"#[deprecated("use v2")]"#)).unwrap().into_inner();
+        assert_eq!(&*attr.name(), "deprecated");
+        assert_eq!(attr.arg(), Some("use v2".into()));
+
+        let attr = attribute().parse(tokens("#[owners(alice, bob-team)]")).unwrap().into_inner();
+        assert_eq!(&*attr.name(), "owners");
+        assert_eq!(
+            attr.args().iter().map(|e| e.clone().into_inner()).collect::<Vec<_>>(),
+            vec!["alice", "bob-team"]
+        );
+    }
+}
@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::rc::Rc;
 use std::sync::Arc;
@@ -5,6 +6,7 @@ use crate::lang::ty::Type;
 use crate::lang::{
     FieldName, Located, Location, ParserError, ParserInput, Span,
 };
+use crate::lang::lexer::{ident, keyword, name, op, punct, Keyword, Token};
 use chumsky::prelude::*;
 use chumsky::Parser;
 use crate::runtime::{EvaluationResult, RuntimeError, RuntimeType};
@@ -37,40 +39,46 @@ pub enum Expr {
 
 impl Located<Expr> {
 
-    pub fn evaluate(self: &Arc<Self>, value: &mut RuntimeValue) -> Result<RuntimeValue, RuntimeError> {
-        match &***self {
-            Expr::SelfLiteral(_) => return Ok(value.clone()),
-            Expr::Value(inner) => return Ok(inner.clone().into_inner()),
-            Expr::Accessor(_, _) => todo!(),
-            Expr::Field(_, _) => todo!(),
-            Expr::Function(_, _) => todo!(),
-            Expr::Add(_, _) => todo!(),
-            Expr::Subtract(_, _) => todo!(),
-            Expr::Multiply(_, _) => todo!(),
-            Expr::Divide(_, _) => todo!(),
-            Expr::LessThan(_, _) => todo!(),
-            Expr::LessThanEqual(_, _) => todo!(),
-            Expr::GreaterThan(lhs, rhs) => {
-                let lhs = Arc::new(*lhs.clone()).evaluate(value)?;
-                let rhs = Arc::new(*rhs.clone()).evaluate(value)?;
-
-                return if let Some(Ordering::Greater) = lhs.partial_cmp(&rhs) {
-                    Ok(true.into())
-                } else {
-                    Ok(false.into())
-                }
-            }
-            Expr::GreaterThanEqual(_, _) => todo!(),
-            Expr::Equal(_, _) => todo!(),
-            Expr::NotEqual(_, _) => todo!(),
-            Expr::Not(_) => todo!(),
-            Expr::LogicalAnd(_, _) => todo!(),
-            Expr::LogicalOr(_, _) => todo!(),
-        };
-
-        todo!()
+    /// Evaluate against `value` as `self`, borrowing rather than cloning
+    /// wherever the result is `self`, a literal, or a field of either.
+    pub fn evaluate<'v>(&'v self, value: &'v Value) -> Result<Cow<'v, Value>, ValueError> {
+        match &**self {
+            Expr::SelfLiteral(_) => Ok(Cow::Borrowed(value)),
+            Expr::Value(inner) => Ok(Cow::Borrowed(inner)),
+            Expr::Accessor(lhs, name) => match lhs.evaluate(value)? {
+                Cow::Borrowed(lhs) => lhs.try_get_object().and_then(|e| e.get(name)).map(Cow::Borrowed).ok_or(ValueError::NoSuchField),
+                Cow::Owned(lhs) => lhs.try_get_object().and_then(|e| e.get(name)).cloned().map(Cow::Owned).ok_or(ValueError::NoSuchField),
+            },
+            Expr::Field(lhs, rhs) => match lhs.evaluate(value)? {
+                Cow::Borrowed(lhs) => rhs.evaluate(lhs),
+                Cow::Owned(lhs) => Ok(Cow::Owned(rhs.evaluate(&lhs)?.into_owned())),
+            },
+            Expr::Function(_, _) => Err(ValueError::UnknownFunction),
+            Expr::Add(lhs, rhs) => Ok(Cow::Owned(lhs.evaluate(value)?.try_add(&*rhs.evaluate(value)?)?)),
+            Expr::Subtract(lhs, rhs) => Ok(Cow::Owned(lhs.evaluate(value)?.try_subtract(&*rhs.evaluate(value)?)?)),
+            Expr::Multiply(lhs, rhs) => Ok(Cow::Owned(lhs.evaluate(value)?.try_multiply(&*rhs.evaluate(value)?)?)),
+            Expr::Divide(lhs, rhs) => Ok(Cow::Owned(lhs.evaluate(value)?.try_divide(&*rhs.evaluate(value)?)?)),
+            Expr::LessThan(lhs, rhs) => compare(lhs, rhs, value, |o| o == Ordering::Less),
+            Expr::LessThanEqual(lhs, rhs) => compare(lhs, rhs, value, |o| o != Ordering::Greater),
+            Expr::GreaterThan(lhs, rhs) => compare(lhs, rhs, value, |o| o == Ordering::Greater),
+            Expr::GreaterThanEqual(lhs, rhs) => compare(lhs, rhs, value, |o| o != Ordering::Less),
+            Expr::Equal(lhs, rhs) => Ok(Cow::Owned((*lhs.evaluate(value)? == *rhs.evaluate(value)?).into())),
+            Expr::NotEqual(lhs, rhs) => Ok(Cow::Owned((*lhs.evaluate(value)? != *rhs.evaluate(value)?).into())),
+            Expr::Not(operand) => Ok(Cow::Owned((!boolean(operand, value)?).into())),
+            Expr::LogicalAnd(lhs, rhs) => Ok(Cow::Owned((boolean(lhs, value)? && boolean(rhs, value)?).into())),
+            Expr::LogicalOr(lhs, rhs) => Ok(Cow::Owned((boolean(lhs, value)? || boolean(rhs, value)?).into())),
+        }
     }
+}
 
+/// Values that cannot be ordered against each other compare as `false`.
+fn compare<'v>(lhs: &'v Located<Expr>, rhs: &'v Located<Expr>, value: &'v Value, test: fn(Ordering) -> bool) -> Result<Cow<'v, Value>, ValueError> {
+    let ordering = (*lhs.evaluate(value)?).partial_cmp(&*rhs.evaluate(value)?);
+    Ok(Cow::Owned(ordering.is_some_and(test).into()))
+}
+
+fn boolean(expr: &Located<Expr>, value: &Value) -> Result<bool, ValueError> {
+    expr.evaluate(value)?.try_get_boolean().ok_or(ValueError::NonBoolean)
 }
 
 impl Expr {
@@ -97,103 +105,104 @@ impl Expr {
             Expr::Field(this, _) => this.is_constant(),
         }
     }
+}
 
-    /*
+impl Located<Expr> {
+
+    /// Fold constant sub-expressions into values.
     pub fn simplify_expr(&self) -> Result<Self, ExprError> {
-        match self {
-            Expr::SelfLiteral(_) => Ok(self.clone()),
-            Expr::Value(_) => Ok(self.clone()),
-            Expr::Add(lhs, rhs) => {
-                let lhs = lhs.simplify_expr()?;
-                let rhs = rhs.simplify_expr()?;
-
-                match (lhs, rhs) {
-                    (Expr::Value(lhs), Expr::Value(rhs)) => {
-                        let location = lhs.span().start..rhs.span().end();
-                        let value = lhs.try_add(&*rhs)?;
-                        Ok(Expr::Value(Located::new(value, location.clone())))
-                    }
-                    _ => Ok(self.clone()),
-                }
-            }
-            Expr::Subtract(lhs, rhs) => {
-                let lhs = lhs.simplify_expr()?;
-                let rhs = rhs.simplify_expr()?;
-
-                match (lhs, rhs) {
-                    (Expr::Value(lhs), Expr::Value(rhs)) => {
-                        let location = lhs.span().start..rhs.span().end();
-                        let value = lhs.try_subtract(&*rhs)?;
-                        Ok(Expr::Value(Located::new(value, location.clone())))
-                    }
-                    _ => Ok(self.clone()),
-                }
-            }
-            Expr::Multiply(lhs, rhs) => {
-                let lhs = lhs.simplify_expr()?;
-                let rhs = rhs.simplify_expr()?;
-
-                match (lhs, rhs) {
-                    (Expr::Value(lhs), Expr::Value(rhs)) => {
-                        let location = lhs.span().start..rhs.span().end();
-                        let value = lhs.try_multiply(&*rhs)?;
-                        Ok(Expr::Value(Located::new(value, location.clone())))
+        let location = self.location();
+        let folded = match &**self {
+            Expr::SelfLiteral(_) | Expr::Value(_) | Expr::Accessor(_, _) => return Ok(self.clone()),
+            Expr::Field(lhs, rhs) => Expr::Field(Box::new(lhs.simplify_expr()?), Box::new(rhs.simplify_expr()?)),
+            Expr::Function(name, operand) => Expr::Function(name.clone(), Box::new(operand.simplify_expr()?)),
+            Expr::Add(lhs, rhs) => return binary(self, lhs, rhs, Expr::Add, |l, r| l.try_add(r)),
+            Expr::Subtract(lhs, rhs) => return binary(self, lhs, rhs, Expr::Subtract, |l, r| l.try_subtract(r)),
+            Expr::Multiply(lhs, rhs) => return binary(self, lhs, rhs, Expr::Multiply, |l, r| l.try_multiply(r)),
+            Expr::Divide(lhs, rhs) => return binary(self, lhs, rhs, Expr::Divide, |l, r| l.try_divide(r)),
+            Expr::LessThan(lhs, rhs) => return comparison(self, lhs, rhs, Expr::LessThan, |o| o == Ordering::Less),
+            Expr::LessThanEqual(lhs, rhs) => return comparison(self, lhs, rhs, Expr::LessThanEqual, |o| o != Ordering::Greater),
+            Expr::GreaterThan(lhs, rhs) => return comparison(self, lhs, rhs, Expr::GreaterThan, |o| o == Ordering::Greater),
+            Expr::GreaterThanEqual(lhs, rhs) => return comparison(self, lhs, rhs, Expr::GreaterThanEqual, |o| o != Ordering::Less),
+            Expr::Equal(lhs, rhs) => return binary(self, lhs, rhs, Expr::Equal, |l, r| Ok((l == r).into())),
+            Expr::NotEqual(lhs, rhs) => return binary(self, lhs, rhs, Expr::NotEqual, |l, r| Ok((l != r).into())),
+            Expr::Not(operand) => {
+                let operand = operand.simplify_expr()?;
+                if let Expr::Value(value) = &*operand {
+                    if let Some(b) = value.try_get_boolean() {
+                        return Ok(Located::new(Expr::Value(Located::new((!b).into(), location.clone())), location));
                     }
-                    _ => Ok(self.clone()),
-                }
-            }
-            Expr::Divide(lhs, rhs) => {
-                let lhs = lhs.simplify_expr()?;
-                let rhs = rhs.simplify_expr()?;
-
-                match (lhs, rhs) {
-                    (Expr::Value(lhs), Expr::Value(rhs)) => {
-                        let location = lhs.span().start..rhs.span().end();
-                        let value = lhs.try_divide(&*rhs)?;
-                        Ok(Expr::Value(Located::new(value, location.clone())))
-                    }
-                    _ => Ok(self.clone()),
                 }
+                Expr::Not(Box::new(operand))
             }
+            Expr::LogicalAnd(lhs, rhs) => return logical(self, lhs, rhs, Expr::LogicalAnd, false),
+            Expr::LogicalOr(lhs, rhs) => return logical(self, lhs, rhs, Expr::LogicalOr, true),
+        };
 
-            Expr::LogicalOr(lhs, rhs) => {
-                let lhs_loc = lhs.location();
-                let lhs = lhs.simplify_expr()?;
+        Ok(Located::new(folded, location))
+    }
 
-                if let Expr::Value(lhs) = &lhs {
-                    //if let Value::Boolean(b) = **lhs {
-                    if lhs.is_boolean() {
-                        if b {
-                            return Ok(Expr::Value(lhs.clone()));
-                        }
-                    }
-                }
+    pub fn try_get_constant(&self) -> Option<&Value> {
+        if let Expr::Value(value) = &**self {
+            Some(value)
+        } else {
+            None
+        }
+    }
+}
 
-                let rhs_loc = rhs.location();
-                let rhs = rhs.simplify_expr()?;
-
-                if let Expr::Value(rhs) = &rhs {
-                    if let Value::Boolean(b) = **rhs {
-                        if b {
-                            return Ok(Expr::Value(rhs.clone()));
-                        } else {
-                            return Ok(Expr::Value(Located::new(
-                                Value::Boolean(false),
-                                lhs_loc.span().start()..rhs_loc.span().end(),
-                            )));
-                        }
-                    }
-                }
+type BinaryExpr = fn(Box<Located<Expr>>, Box<Located<Expr>>) -> Expr;
+
+fn binary(
+    expr: &Located<Expr>,
+    lhs: &Located<Expr>,
+    rhs: &Located<Expr>,
+    op: BinaryExpr,
+    fold: impl Fn(&Value, &Value) -> Result<Value, ValueError>,
+) -> Result<Located<Expr>, ExprError> {
+    let lhs = lhs.simplify_expr()?;
+    let rhs = rhs.simplify_expr()?;
+
+    if let (Some(l), Some(r)) = (lhs.try_get_constant(), rhs.try_get_constant()) {
+        let value = fold(l, r)?;
+        return Ok(Located::new(Expr::Value(Located::new(value, expr.location())), expr.location()));
+    }
 
-                Ok(Expr::LogicalOr(
-                    Box::new(Located::new(lhs.clone(), lhs_loc)),
-                    Box::new(Located::new(rhs.clone(), rhs_loc)),
-                ))
-            }
-            _ => Ok(self.clone()),
+    Ok(Located::new(op(Box::new(lhs), Box::new(rhs)), expr.location()))
+}
+
+fn comparison(
+    expr: &Located<Expr>,
+    lhs: &Located<Expr>,
+    rhs: &Located<Expr>,
+    op: BinaryExpr,
+    test: fn(Ordering) -> bool,
+) -> Result<Located<Expr>, ExprError> {
+    // as when evaluated, values which cannot be ordered compare as `false`
+    binary(expr, lhs, rhs, op, |l, r| Ok(l.partial_cmp(r).is_some_and(test).into()))
+}
+
+/// Fold `&&` (short-circuiting on `false`) and `||` (on `true`).
+fn logical(
+    expr: &Located<Expr>,
+    lhs: &Located<Expr>,
+    rhs: &Located<Expr>,
+    op: BinaryExpr,
+    short_circuit: bool,
+) -> Result<Located<Expr>, ExprError> {
+    let lhs = lhs.simplify_expr()?;
+    let rhs = rhs.simplify_expr()?;
+
+    let constant = |e: &Located<Expr>| e.try_get_constant().and_then(|v| v.try_get_boolean());
+
+    match (constant(&lhs), constant(&rhs)) {
+        (Some(b), _) | (_, Some(b)) if b == short_circuit => {
+            Ok(Located::new(Expr::Value(Located::new(b.into(), expr.location())), expr.location()))
         }
+        (Some(_), _) => Ok(rhs),
+        (_, Some(_)) => Ok(lhs),
+        _ => Ok(Located::new(op(Box::new(lhs), Box::new(rhs)), expr.location())),
     }
-     */
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -212,134 +221,98 @@ impl From<ValueError> for ExprError {
 pub enum ValueError {
     NonArithmatic,
     DivideByZero,
+    Overflow,
+    NonBoolean,
+    NoSuchField,
+    UnknownFunction,
+}
+
+enum Numeric {
+    Integer(i64, i64),
+    Decimal(f64, f64),
+}
+
+fn numeric(lhs: &Value, rhs: &Value) -> Result<Numeric, ValueError> {
+    match (lhs.try_get_integer(), rhs.try_get_integer(), lhs.try_get_decimal(), rhs.try_get_decimal()) {
+        (Some(lhs), Some(rhs), _, _) => Ok(Numeric::Integer(lhs, rhs)),
+        (_, Some(rhs), Some(lhs), _) => Ok(Numeric::Decimal(lhs, rhs as f64)),
+        (Some(lhs), _, _, Some(rhs)) => Ok(Numeric::Decimal(lhs as f64, rhs)),
+        (_, _, Some(lhs), Some(rhs)) => Ok(Numeric::Decimal(lhs, rhs)),
+        _ => Err(ValueError::NonArithmatic),
+    }
 }
 
-/*
 impl Value {
     pub fn try_add(&self, other: &Self) -> Result<Self, ValueError> {
-        match (self, other) {
-            (Self::Integer(lhs), Self::Integer(rhs)) => Ok(Self::Integer(lhs + rhs)),
-            (Self::Decimal(lhs), Self::Decimal(rhs)) => Ok(Self::Decimal(lhs + rhs)),
-            (Self::Decimal(lhs), Self::Integer(rhs)) => Ok(Self::Decimal(lhs + *rhs as f64)),
-            (Self::Integer(lhs), Self::Decimal(rhs)) => Ok(Self::Decimal(*lhs as f64 + rhs)),
-
-            _ => Err(ValueError::NonArithmatic),
+        match numeric(self, other)? {
+            Numeric::Integer(lhs, rhs) => Ok(lhs.checked_add(rhs).ok_or(ValueError::Overflow)?.into()),
+            Numeric::Decimal(lhs, rhs) => Ok((lhs + rhs).into()),
         }
     }
 
     pub fn try_subtract(&self, other: &Self) -> Result<Self, ValueError> {
-        match (self, other) {
-            (Self::Integer(lhs), Self::Integer(rhs)) => Ok(Self::Integer(lhs - rhs)),
-            (Self::Decimal(lhs), Self::Decimal(rhs)) => Ok(Self::Decimal(lhs - rhs)),
-            (Self::Decimal(lhs), Self::Integer(rhs)) => Ok(Self::Decimal(lhs - *rhs as f64)),
-            (Self::Integer(lhs), Self::Decimal(rhs)) => Ok(Self::Decimal(*lhs as f64 - rhs)),
-            _ => Err(ValueError::NonArithmatic),
+        match numeric(self, other)? {
+            Numeric::Integer(lhs, rhs) => Ok(lhs.checked_sub(rhs).ok_or(ValueError::Overflow)?.into()),
+            Numeric::Decimal(lhs, rhs) => Ok((lhs - rhs).into()),
         }
     }
 
     pub fn try_multiply(&self, other: &Self) -> Result<Self, ValueError> {
-        match (self, other) {
-            (Self::Integer(lhs), Self::Integer(rhs)) => Ok(Self::Integer(lhs * rhs)),
-            (Self::Decimal(lhs), Self::Decimal(rhs)) => Ok(Self::Decimal(lhs * rhs)),
-            (Self::Decimal(lhs), Self::Integer(rhs)) => Ok(Self::Decimal(lhs * *rhs as f64)),
-            (Self::Integer(lhs), Self::Decimal(rhs)) => Ok(Self::Decimal(*lhs as f64 * rhs)),
-            _ => Err(ValueError::NonArithmatic),
+        match numeric(self, other)? {
+            Numeric::Integer(lhs, rhs) => Ok(lhs.checked_mul(rhs).ok_or(ValueError::Overflow)?.into()),
+            Numeric::Decimal(lhs, rhs) => Ok((lhs * rhs).into()),
         }
     }
 
     pub fn try_divide(&self, other: &Self) -> Result<Self, ValueError> {
-        match (self, other) {
-            (Self::Integer(lhs), Self::Integer(rhs)) => {
-                if *rhs == 0 {
-                    return Err(ValueError::DivideByZero);
-                }
-                Ok(Self::Integer(lhs / rhs))
-            }
-            (Self::Decimal(lhs), Self::Decimal(rhs)) => {
-                if *rhs == 0.0 {
-                    return Err(ValueError::DivideByZero);
-                }
-                Ok(Self::Decimal(lhs / rhs))
-            }
-            (Self::Decimal(lhs), Self::Integer(rhs)) => {
-                if *rhs == 0 {
-                    return Err(ValueError::DivideByZero);
-                }
-                Ok(Self::Decimal(lhs / *rhs as f64))
-            }
-            (Self::Integer(lhs), Self::Decimal(rhs)) => {
-                if *rhs == 0.0 {
-                    return Err(ValueError::DivideByZero);
+        match numeric(self, other)? {
+            Numeric::Integer(_, 0) => Err(ValueError::DivideByZero),
+            Numeric::Integer(lhs, rhs) => Ok(lhs.checked_div(rhs).ok_or(ValueError::Overflow)?.into()),
+            Numeric::Decimal(lhs, rhs) => {
+                if rhs == 0.0 {
+                    Err(ValueError::DivideByZero)
+                } else {
+                    Ok((lhs / rhs).into())
                 }
-                Ok(Self::Decimal(*lhs as f64 / rhs))
             }
-            _ => Err(ValueError::NonArithmatic),
         }
     }
 }
- */
-
-pub fn op(op: &str) -> impl Parser<ParserInput, &str, Error=ParserError> + Clone {
-    just(op).padded()
-}
 
 pub fn boolean_literal() -> impl Parser<ParserInput, Located<Expr>, Error=ParserError> + Clone {
-    just("true")
-        .padded()
-        .map_with_span(|_, span: Span| {
+    keyword(Keyword::True)
+        .to(true)
+        .or(keyword(Keyword::False).to(false))
+        .map_with_span(|value, span: Span| {
             Located::new(
-                Expr::Value(Located::new(true.into(), span.clone())),
-                span.clone(),
+                Expr::Value(Located::new(value.into(), span.clone())),
+                span,
             )
         })
-        .or(just("false").padded().map_with_span(|_, span: Span| {
-            Located::new(
-                Expr::Value(Located::new(false.into(), span.clone())),
-                span.clone(),
-            )
-        }))
 }
 
+#[allow(clippy::result_large_err)]
 pub fn integer_literal() -> impl Parser<ParserInput, Located<Expr>, Error=ParserError> + Clone {
-    text::int::<char, ParserError>(10)
+    select! { Token::Integer(s) => s }
         .map_with_span(|s: String, span| Located::new(s.parse::<i64>().unwrap().into(), span))
-        .padded()
         .map_with_span(|value, span| Located::new(Expr::Value(value), span))
 }
 
+#[allow(clippy::result_large_err)]
 pub fn decimal_literal() -> impl Parser<ParserInput, Located<Expr>, Error=ParserError> + Clone {
-    text::int(10)
-        .then(just('.').then(text::int(10)))
-        .padded()
-        .map_with_span(
-            |(integral, (_dot, decimal)): (String, (char, String)), span| {
-                Located::new(
-                    format!("{}.{}", integral, decimal).parse::<f64>().unwrap().into(),
-                    span,
-                )
-            },
-        )
+    select! { Token::Decimal(s) => s }
+        .map_with_span(|s: String, span| Located::new(s.parse::<f64>().unwrap().into(), span))
         .map_with_span(|value, span| Located::new(Expr::Value(value), span))
 }
 
+#[allow(clippy::result_large_err)]
 pub fn string_literal() -> impl Parser<ParserInput, Located<Expr>, Error=ParserError> + Clone {
-    just('"')
-        .ignored()
-        .then(
-            filter(|c: &char| *c != '"')
-                .repeated()
-                .collect::<String>()
-        )
-        .then(
-            just('"')
-                .ignored()
-        )
-        .padded()
-        .map_with_span(|((_, x), _), span: Span| {
+    select! { Token::Str(x) => x }
+        .map_with_span(|x, span: Span| {
             Located::new(
                 Expr::Value(
                     Located::new(
-                            x.into(),
+                        x.into(),
                         span.clone(),
                     )
                 ),
@@ -349,16 +322,15 @@ pub fn string_literal() -> impl Parser<ParserInput, Located<Expr>, Error=ParserE
 }
 
 pub fn self_literal() -> impl Parser<ParserInput, Located<Expr>, Error=ParserError> + Clone {
-    just("self").padded().map_with_span(|v, span: Span| {
+    keyword(Keyword::SelfValue).map_with_span(|_, span: Span| {
         Located::new(Expr::SelfLiteral(Location::from(span.clone())), span)
     })
 }
 
 pub fn field_expr() -> impl Parser<ParserInput, Located<Expr>, Error=ParserError> + Clone {
-    text::ident().map_with_span(|v, span| Located::new(v, span))
+    ident()
         .then(
-            op(":")
-                .padded()
+            punct(':')
                 .ignored()
         )
         .then(
@@ -394,18 +366,26 @@ pub fn atom() -> impl Parser<ParserInput, Located<Expr>, Error=ParserError> + Cl
 }
 
 pub fn expr() -> impl Parser<ParserInput, Located<Expr>, Error=ParserError> + Clone {
-    recursive(|expr| parenthesized_expr(expr.clone()).or(logical_or(expr)))
+    recursive(logical_or)
+}
+
+/// An operand of arithmetic, which is an atom or an expression in
+/// parentheses, followed by any `.field` accessors.
+pub fn primary(
+    expr: impl Parser<ParserInput, Located<Expr>, Error=ParserError> + Clone,
+) -> impl Parser<ParserInput, Located<Expr>, Error=ParserError> + Clone {
+    atom().or(parenthesized_expr(expr))
+        .then(punct('.').ignore_then(name()).repeated())
+        .foldl(|lhs, name| {
+            let span = lhs.span().start()..name.span().end();
+            Located::new(Expr::Accessor(Box::new(lhs), name), span)
+        })
 }
 
 pub fn parenthesized_expr(
     expr: impl Parser<ParserInput, Located<Expr>, Error=ParserError> + Clone,
 ) -> impl Parser<ParserInput, Located<Expr>, Error=ParserError> + Clone {
-    just("(")
-        .padded()
-        .ignored()
-        .then(expr)
-        .then(just(")").padded().ignored())
-        .map(|((_left_paren, expr), _right_paren)| expr)
+    expr.delimited_by(punct('('), punct(')'))
 }
 
 pub fn logical_or(
@@ -430,6 +410,8 @@ pub fn logical_and(
         })
 }
 
+/// A comparison of two additive expressions, binding tighter than `&&`
+/// and `||`, so `self > 1 && self < 5` compares twice.
 pub fn relational_expr(
     expr: impl Parser<ParserInput, Located<Expr>, Error=ParserError> + Clone,
 ) -> impl Parser<ParserInput, Located<Expr>, Error=ParserError> + Clone {
@@ -451,12 +433,12 @@ pub fn relational_expr(
                     .map_with_span(|_, span| Located::new(Expr::Equal as fn(_, _) -> _, span)))
                 .or(op("!=")
                     .map_with_span(|_, span| Located::new(Expr::NotEqual as fn(_, _) -> _, span)))
-                .then(expr)
+                .then(additive_expr(expr))
                 .or_not(),
         )
         .map(|(lhs, rhs)| {
             if let Some((op, rhs)) = rhs {
-                let span = op.span().start()..rhs.span().end;
+                let span = lhs.span().start()..rhs.span().end;
                 Located::new(op(Box::new(lhs), Box::new(rhs)), span)
             } else {
                 lhs
@@ -485,13 +467,13 @@ pub fn additive_expr(
 pub fn multiplicative_expr(
     expr: impl Parser<ParserInput, Located<Expr>, Error=ParserError> + Clone,
 ) -> impl Parser<ParserInput, Located<Expr>, Error=ParserError> + Clone {
-    atom()
+    primary(expr.clone())
         .then(
             op("*")
                 .map_with_span(|_, span| Located::new(Expr::Multiply as fn(_, _) -> _, span))
                 .or(op("/")
                     .map_with_span(|_, span| Located::new(Expr::Divide as fn(_, _) -> _, span)))
-                .then(atom())
+                .then(primary(expr))
                 .repeated(),
         )
         .foldl(|lhs, (op, rhs)| {
@@ -503,15 +485,66 @@ pub fn multiplicative_expr(
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::lang::lexer::tokens;
+
+    #[test]
+    fn parse_accessor() {
+        let expr = expr().then_ignore(end()).parse(tokens("self.owner.type == \"user\"")).unwrap().into_inner();
+        if let Expr::Equal(lhs, _) = expr {
+            assert!(matches!(
+                &**lhs,
+                Expr::Accessor(inner, name) if &**name == "type" && matches!(&***inner, Expr::Accessor(_, owner) if &**owner == "owner")
+            ));
+        } else {
+            panic!("expected an equality");
+        }
+    }
+
+    #[test]
+    fn parse_dashed_accessor() {
+        let expr = punct('$').ignore_then(parenthesized_expr(expr())).then_ignore(end())
+            .parse(tokens("$(self.replica-count > 1)")).unwrap().into_inner();
+        if let Expr::GreaterThan(lhs, _) = expr {
+            assert!(matches!(
+                &**lhs,
+                Expr::Accessor(inner, name) if &**name == "replica-count" && matches!(&***inner, Expr::SelfLiteral(_))
+            ));
+        } else {
+            panic!("expected a comparison");
+        }
+    }
+
+    #[test]
+    fn fold_incomparable_as_false() {
+        let expr = expr().then_ignore(end()).parse(tokens(r#"1 > "a""#)).unwrap();
+        let value = Value::from(0);
+
+        assert_eq!(expr.evaluate(&value).unwrap().try_get_boolean(), Some(false));
+        assert!(matches!(
+            expr.simplify_expr().unwrap().into_inner(),
+            Expr::Value(folded) if folded.try_get_boolean() == Some(false)
+        ));
+    }
+
+    #[test]
+    fn parse_relational_precedence() {
+        let expr = expr().then_ignore(end()).parse(tokens("self > 1 && 2 > 1")).unwrap().into_inner();
+        if let Expr::LogicalAnd(lhs, rhs) = expr {
+            assert!(matches!(&**lhs, Expr::GreaterThan(..)));
+            assert!(matches!(&**rhs, Expr::GreaterThan(..)));
+        } else {
+            panic!("expected a logical and");
+        }
+    }
 
     #[test]
     fn parse_self() {
         let ty = expr()
-            .parse(
+            .parse(tokens(
                 r#"
             self
         "#,
-            )
+            ))
             .unwrap()
             .into_inner();
 
@@ -521,9 +554,9 @@ mod test {
     #[test]
     fn parse_string() {
         let ty = expr()
-            .parse(r#"
+            .parse(tokens(r#"
                     "howdy"
-            "#)
+            "#))
             .unwrap()
             .into_inner();
 
@@ -555,11 +588,11 @@ mod test {
     #[test]
     fn parse_decimal_literal() {
         let ty = expr()
-            .parse(
+            .parse(tokens(
                 r#"
             42.1415
         "#,
-            )
+            ))
             .unwrap()
             .into_inner();
 
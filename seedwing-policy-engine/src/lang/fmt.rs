@@ -0,0 +1,651 @@
+//! Render parsed policies back into canonical `.dog` source.
+//!
+//! The grammar folds `&&` and `||` with a full expression on their
+//! right-hand side, so parentheses are only emitted where they are needed
+//! to reproduce the same tree when re-parsed.
+//!
+//! Comments leading or trailing a statement or field are kept in the tree.
+//! Those between the terms of a join or meet are not, so they are looked up
+//! by location in the source and put on their own line before the operator.
+//! Formatting fails, rather than drop source text, on any comment which was
+//! not rendered.
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use chumsky::{Error, Parser};
+use walkdir::WalkDir;
+use crate::lang::attr::Attribute;
+use crate::lang::expr::Expr;
+use crate::lang::lexer::{lexer, Token};
+use crate::lang::ty::{Field, Type, TypeDefn, TypeName};
+use crate::lang::{CompilationUnit, Located, ParserError, PolicyParser, Source, Use};
+use crate::value::Value;
+
+const INDENT: &str = "    ";
+
+#[derive(Debug)]
+pub enum FormatError {
+    Io(PathBuf, std::io::Error),
+    Parser(PathBuf, Vec<ParserError>),
+}
+
+/// Parse and re-render a single source.
+pub fn format_source<S: Into<Source> + Clone>(source: S, content: &str) -> Result<String, Vec<ParserError>> {
+    let unit = PolicyParser::default().parse(source, content)?;
+
+    // parsed without errors, so lexed without errors too
+    let comments = lexer().parse(content)
+        .map(|(tokens, _)| tokens)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|(token, span)| match token {
+            Token::Comment(comment) | Token::TrailingComment(comment) => Some(Located::new(comment, span)),
+            _ => None,
+        })
+        .collect();
+
+    Printer::new(comments).print(&unit)
+}
+
+/// Format every `.dog` file beneath `dir`, returning the files whose
+/// content changed. When `check` is set, files are left untouched.
+pub fn fmt_directory(dir: &Path, check: bool) -> Result<Vec<PathBuf>, FormatError> {
+    let mut changed = Vec::new();
+
+    for entry in WalkDir::new(dir).into_iter().filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if !entry.file_type().is_file() || path.extension().is_none_or(|e| e != "dog") {
+            continue;
+        }
+
+        let mut content = String::new();
+        File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut content))
+            .map_err(|e| FormatError::Io(path.into(), e))?;
+
+        let source = path.to_string_lossy().to_string();
+        let formatted = format_source(source, &content)
+            .map_err(|e| FormatError::Parser(path.into(), e))?;
+
+        if formatted != content {
+            if !check {
+                File::create(path)
+                    .and_then(|mut file| file.write_all(formatted.as_bytes()))
+                    .map_err(|e| FormatError::Io(path.into(), e))?;
+            }
+            changed.push(path.into());
+        }
+    }
+
+    Ok(changed)
+}
+
+/// Renders the tree of one source, given every comment within it.
+struct Printer {
+    comments: Vec<Located<String>>,
+    /// Where each comment rendered so far starts.
+    printed: RefCell<HashSet<usize>>,
+}
+
+impl Printer {
+    fn new(comments: Vec<Located<String>>) -> Self {
+        Self {
+            comments,
+            printed: Default::default(),
+        }
+    }
+
+    fn print(&self, unit: &CompilationUnit) -> Result<String, Vec<ParserError>> {
+        let out = self.unit(unit);
+
+        let printed = self.printed.borrow();
+        let lost = self.comments.iter()
+            .filter(|e| !printed.contains(&e.span().start))
+            .map(|e| ParserError::custom(e.span(), "comment cannot be kept here when formatting"))
+            .collect::<Vec<_>>();
+
+        if lost.is_empty() {
+            Ok(out)
+        } else {
+            Err(lost)
+        }
+    }
+
+    fn push_comments(&self, out: &mut String, comments: &[Located<String>], indent: usize) {
+        for comment in comments {
+            self.printed.borrow_mut().insert(comment.span().start);
+            out.push_str(&INDENT.repeat(indent));
+            out.push_str("//");
+            out.push_str(comment);
+            out.push('\n');
+        }
+    }
+
+    fn push_trailing_comment(&self, out: &mut String, comment: Option<&Located<String>>) {
+        if let Some(comment) = comment {
+            self.printed.borrow_mut().insert(comment.span().start);
+            out.push_str(" //");
+            out.push_str(comment);
+        }
+    }
+
+    fn unit(&self, unit: &CompilationUnit) -> String {
+        let mut out = String::new();
+
+        for each in unit.uses() {
+            out.push_str(&self.use_statement(each));
+        }
+
+        for defn in unit.types() {
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            out.push_str(&self.type_defn(defn));
+        }
+
+        if !unit.trailing_comments().is_empty() {
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            self.push_comments(&mut out, unit.trailing_comments(), 0);
+        }
+
+        out
+    }
+
+    fn use_statement(&self, each: &Use) -> String {
+        let mut out = String::new();
+        self.push_comments(&mut out, each.comments(), 0);
+        out.push_str("use ");
+        out.push_str(&format_type_name(&each.type_name()));
+        if let Some(alias) = each.alias() {
+            out.push_str(" as ");
+            out.push_str(&alias);
+        }
+        self.push_trailing_comment(&mut out, each.trailing_comment());
+        out.push('\n');
+        out
+    }
+
+    fn type_defn(&self, defn: &TypeDefn) -> String {
+        let mut out = String::new();
+        self.push_comments(&mut out, defn.comments(), 0);
+        push_attributes(&mut out, defn.attributes(), 0);
+        if defn.visibility().is_public() {
+            out.push_str("pub ");
+        }
+        out.push_str("type ");
+        out.push_str(&defn.name());
+        if !matches!(&**defn.ty(), Type::Nothing) {
+            out.push_str(" = ");
+            out.push_str(&self.ty(defn.ty(), 0));
+        }
+        self.push_trailing_comment(&mut out, defn.trailing_comment());
+        out.push('\n');
+        out
+    }
+
+    /// Render a type in a position that accepts an `else` clause.
+    fn ty(&self, ty: &Located<Type>, indent: usize) -> String {
+        match &**ty {
+            Type::Message(inner, message) => {
+                format!("{} else \"{}\"", self.operand(inner, false, indent), &**message)
+            }
+            _ => self.operand(ty, false, indent),
+        }
+    }
+
+    fn parenthesized(&self, ty: &Located<Type>, indent: usize) -> String {
+        format!("({})", self.ty(ty, indent))
+    }
+
+    /// Render the operand of `&&` or `||`. When `followed` is set, more
+    /// operators come after it, so a trailing right-hand side must be
+    /// parenthesized to stop the parser from folding them into it.
+    fn operand(&self, ty: &Located<Type>, followed: bool, indent: usize) -> String {
+        match &**ty {
+            Type::Join(..) => self.chain(ty, followed, indent),
+            Type::Meet(..) => self.chain(ty, followed, indent),
+            Type::Message(..) => self.parenthesized(ty, indent),
+            _ => self.primary(ty, indent),
+        }
+    }
+
+    fn chain(&self, ty: &Located<Type>, followed: bool, indent: usize) -> String {
+        let op = if matches!(&**ty, Type::Join(..)) { "||" } else { "&&" };
+
+        // flatten the left spine produced by folding
+        let mut rhs = Vec::new();
+        let mut lhs = ty;
+        while let Some((l, r)) = split(lhs, op) {
+            rhs.push(r);
+            lhs = l;
+        }
+        rhs.reverse();
+
+        let mut out = match &**lhs {
+            Type::Meet(..) if op == "||" => self.chain(lhs, true, indent),
+            Type::Join(..) | Type::Meet(..) | Type::Message(..) => self.parenthesized(lhs, indent),
+            _ => self.primary(lhs, indent),
+        };
+
+        let last = rhs.len() - 1;
+        let mut previous = lhs;
+        for (i, each) in rhs.iter().enumerate() {
+            let comments = self.between(previous, each);
+            if comments.is_empty() {
+                out.push(' ');
+            } else {
+                out.push('\n');
+                self.push_comments(&mut out, &comments, indent + 1);
+                out.push_str(&INDENT.repeat(indent + 1));
+            }
+            out.push_str(&format!("{} ", op));
+            if i < last || followed {
+                out.push_str(&self.parenthesized(each, indent));
+            } else {
+                out.push_str(&self.operand(each, false, indent));
+            }
+            previous = each;
+        }
+
+        out
+    }
+
+    /// Comments between two terms, around the operator joining them.
+    fn between(&self, lhs: &Located<Type>, rhs: &Located<Type>) -> Vec<Located<String>> {
+        self.comments.iter()
+            .filter(|e| e.span().start >= lhs.span().end && e.span().end <= rhs.span().start)
+            .cloned()
+            .collect()
+    }
+
+    fn primary(&self, ty: &Located<Type>, indent: usize) -> String {
+        match &**ty {
+            Type::Anything => "anything".into(),
+            Type::Nothing => "nothing".into(),
+            Type::Ref(name) => format_type_name(name),
+            Type::Const(value) => format_value(value),
+            Type::Object(obj) => {
+                if obj.fields().is_empty() && obj.trailing_comments().is_empty() {
+                    return "{}".into();
+                }
+                let mut out = String::from("{\n");
+                for field in obj.fields() {
+                    out.push_str(&self.field(field, indent + 1));
+                }
+                self.push_comments(&mut out, obj.trailing_comments(), indent + 1);
+                out.push_str(&INDENT.repeat(indent));
+                out.push('}');
+                out
+            }
+            Type::Expr(expr) => format!("$({})", format_expr(expr)),
+            Type::Functional(name, inner) => {
+                let inner = inner.as_ref().map(|e| self.ty(e, indent)).unwrap_or_default();
+                format!("{}({})", format_type_name(name), inner)
+            }
+            Type::List(inner) => format!("[{}]", self.ty(inner, indent)),
+            Type::Join(..) | Type::Meet(..) | Type::Message(..) => self.parenthesized(ty, indent),
+        }
+    }
+
+    fn field(&self, field: &Field, indent: usize) -> String {
+        let mut out = String::new();
+        self.push_comments(&mut out, field.comments(), indent);
+        push_attributes(&mut out, field.attributes(), indent);
+        out.push_str(&INDENT.repeat(indent));
+        out.push_str(field.name());
+        out.push_str(": ");
+        out.push_str(&self.ty(field.ty(), indent));
+        out.push(',');
+        self.push_trailing_comment(&mut out, field.trailing_comment());
+        out.push('\n');
+        out
+    }
+}
+
+fn split<'t>(ty: &'t Located<Type>, op: &str) -> Option<(&'t Located<Type>, &'t Located<Type>)> {
+    match (&**ty, op) {
+        (Type::Join(l, r), "||") | (Type::Meet(l, r), "&&") => Some((l, r)),
+        _ => None,
+    }
+}
+
+fn push_attributes(out: &mut String, attributes: &[Located<Attribute>], indent: usize) {
+    for attr in attributes {
+        out.push_str(&INDENT.repeat(indent));
+        out.push_str(&format_attribute(attr));
+        out.push('\n');
+    }
+}
+
+pub fn format_attribute(attr: &Attribute) -> String {
+    let mut out = format!("#[{}", &*attr.name());
+    if !attr.args().is_empty() {
+        let args = attr.args().iter()
+            .map(|arg| {
+                let bare = !arg.is_empty() && arg.chars().all(|c| {
+                    c.is_alphanumeric() || c == '_' || c == '-' || c == '.'
+                });
+                if bare {
+                    arg.clone().into_inner()
+                } else {
+                    format!("\"{}\"", &**arg)
+                }
+            })
+            .collect::<Vec<_>>();
+        out.push('(');
+        out.push_str(&args.join(", "));
+        out.push(')');
+    }
+    out.push(']');
+    out
+}
+
+/// Type names are written without the leading `::`, which the parser
+/// accepts but does not distinguish.
+pub fn format_type_name(name: &TypeName) -> String {
+    let mut segments = name.package()
+        .map(|package| {
+            package.path().iter()
+                .map(|e| (***e).clone())
+                .collect::<Vec<String>>()
+        })
+        .unwrap_or_default();
+    segments.push(name.name());
+    segments.join("::")
+}
+
+pub fn format_value(value: &Value) -> String {
+    if let Some(inner) = value.try_get_string() {
+        format!("\"{}\"", inner)
+    } else if let Some(inner) = value.try_get_decimal() {
+        // `integral.fraction`, as lexed, without exponents
+        let decimal = inner.to_string();
+        if decimal.contains('.') {
+            decimal
+        } else {
+            format!("{}.0", decimal)
+        }
+    } else {
+        value.to_string()
+    }
+}
+
+#[derive(PartialEq, PartialOrd, Copy, Clone)]
+enum Precedence {
+    Or,
+    And,
+    Relational,
+    Additive,
+    Multiplicative,
+    Atom,
+}
+
+fn precedence(expr: &Expr) -> Precedence {
+    match expr {
+        Expr::LogicalOr(..) => Precedence::Or,
+        Expr::LogicalAnd(..) => Precedence::And,
+        Expr::LessThan(..)
+        | Expr::LessThanEqual(..)
+        | Expr::GreaterThan(..)
+        | Expr::GreaterThanEqual(..)
+        | Expr::Equal(..)
+        | Expr::NotEqual(..) => Precedence::Relational,
+        Expr::Add(..) | Expr::Subtract(..) => Precedence::Additive,
+        Expr::Multiply(..) | Expr::Divide(..) => Precedence::Multiplicative,
+        _ => Precedence::Atom,
+    }
+}
+
+fn binary(expr: &Expr) -> Option<(&'static str, &Expr, &Expr)> {
+    let (op, l, r) = match expr {
+        Expr::LogicalOr(l, r) => ("||", l, r),
+        Expr::LogicalAnd(l, r) => ("&&", l, r),
+        Expr::LessThan(l, r) => ("<", l, r),
+        Expr::LessThanEqual(l, r) => ("<=", l, r),
+        Expr::GreaterThan(l, r) => (">", l, r),
+        Expr::GreaterThanEqual(l, r) => (">=", l, r),
+        Expr::Equal(l, r) => ("==", l, r),
+        Expr::NotEqual(l, r) => ("!=", l, r),
+        Expr::Add(l, r) => ("+", l, r),
+        Expr::Subtract(l, r) => ("-", l, r),
+        Expr::Multiply(l, r) => ("*", l, r),
+        Expr::Divide(l, r) => ("/", l, r),
+        _ => return None,
+    };
+    Some((op, l, r))
+}
+
+pub fn format_expr(expr: &Expr) -> String {
+    expr_operand(expr, Precedence::Or, false)
+}
+
+/// Render an expression where at least `min` precedence is required.
+fn expr_operand(expr: &Expr, min: Precedence, followed: bool) -> String {
+    let prec = precedence(expr);
+    if prec < min {
+        return format!("({})", format_expr(expr));
+    }
+
+    let (op, lhs, rhs) = if let Some(binary) = binary(expr) {
+        binary
+    } else {
+        return expr_atom(expr);
+    };
+
+    match prec {
+        Precedence::Or | Precedence::And => {
+            // flatten the left spine, as with type joins and meets
+            let mut rest = vec![rhs];
+            let mut left = lhs;
+            while let Some((inner_op, l, r)) = binary(left) {
+                if inner_op != op {
+                    break;
+                }
+                rest.push(r);
+                left = l;
+            }
+            rest.reverse();
+
+            let first_min = if prec == Precedence::Or { Precedence::And } else { Precedence::Relational };
+            let mut out = expr_operand(left, first_min, true);
+            let last = rest.len() - 1;
+            for (i, each) in rest.iter().enumerate() {
+                out.push_str(&format!(" {} ", op));
+                if i < last || followed {
+                    out.push_str(&format!("({})", format_expr(each)));
+                } else {
+                    out.push_str(&format_expr(each));
+                }
+            }
+            out
+        }
+        Precedence::Relational => {
+            format!("{} {} {}",
+                    expr_operand(lhs, Precedence::Additive, true),
+                    op,
+                    expr_operand(rhs, Precedence::Additive, true))
+        }
+        Precedence::Additive => {
+            format!("{} {} {}",
+                    expr_operand(lhs, Precedence::Additive, true),
+                    op,
+                    expr_operand(rhs, Precedence::Multiplicative, true))
+        }
+        _ => {
+            format!("{} {} {}",
+                    expr_operand(lhs, Precedence::Multiplicative, true),
+                    op,
+                    expr_operand(rhs, Precedence::Atom, true))
+        }
+    }
+}
+
+fn expr_atom(expr: &Expr) -> String {
+    match expr {
+        Expr::SelfLiteral(_) => "self".into(),
+        Expr::Value(value) => format_value(value),
+        Expr::Accessor(lhs, name) => format!("{}.{}", expr_operand(lhs, Precedence::Atom, true), &**name),
+        Expr::Field(lhs, rhs) => format!("{}: {}", format_expr(lhs), format_expr(rhs)),
+        Expr::Function(name, operand) => format!("{}({})", &**name, format_expr(operand)),
+        Expr::Not(operand) => format!("!{}", expr_operand(operand, Precedence::Atom, true)),
+        _ => format!("({})", format_expr(expr)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use super::*;
+
+    fn assert_round_trip(src: &str) {
+        let formatted = format_source("test.dog", src).unwrap();
+        assert_eq!(formatted, src);
+    }
+
+    #[test]
+    fn format_canonical_layout() {
+        let formatted = format_source("test.dog", r#"
+            use   foo::bar::bar
+            // licenses we accept
+            use x::y::z   as osi-approved-license
+            #[severity(high)]   #[deprecated("use v2")]
+            pub    type bob = {
+                // in years
+                age: int && $(self>48),
+                name:"Bob",
+                nested: { quux: 4.0 },
+            }
+            type lily
+            type jim = int && $( self >= 0 ) else "must not be negative"
+            // the end
+        "#).unwrap();
+
+        assert_eq!(formatted, r#"use foo::bar::bar
+// licenses we accept
+use x::y::z as osi-approved-license
+
+#[severity(high)]
+#[deprecated("use v2")]
+pub type bob = {
+    // in years
+    age: int && $(self > 48),
+    name: "Bob",
+    nested: {
+        quux: 4.0,
+    },
+}
+
+type lily
+
+type jim = int && $(self >= 0) else "must not be negative"
+
+// the end
+"#);
+
+        assert_round_trip(&formatted);
+    }
+
+    #[test]
+    fn format_preserves_folding() {
+        assert_round_trip("type a = b && (c) && d\n");
+        assert_round_trip("type a = b && (c) || d\n");
+        assert_round_trip("type a = b && c || d\n");
+        assert_round_trip("type a = b && (c else \"no c\")\n");
+        assert_round_trip("type a = [int else \"ints only\"]\n");
+        assert_round_trip("type a = $(1 + 2 * 3 - 4 > 0 || self == \"x\")\n");
+        assert_round_trip("type a = $(self.owner.age * 2 > self.age)\n");
+    }
+
+    #[test]
+    fn format_decimals() {
+        assert_eq!(format_value(&1e23.into()), "100000000000000000000000.0");
+        assert_eq!(format_value(&1e-7.into()), "0.0000001");
+        assert_round_trip("type a = 100000000000000000000000.0\n");
+        assert_round_trip("type a = $(self > 0.0000001 && self < 4.0)\n");
+    }
+
+    #[test]
+    fn format_relational_precedence() {
+        assert_round_trip("type a = $(self > 1 && 2 > 1 || self == 0)\n");
+        assert_round_trip("type a = $(self > (1 && 2 > 1))\n");
+        assert_round_trip("type a = $((self || 1) && 2 > 1)\n");
+        assert_round_trip("type a = $((1 + 2) * 3 > self)\n");
+        // a parenthesized operand no longer stops the rest from folding into it
+        assert_eq!(format_source("test.dog", "type a = $(self > (1) && 2 > 1)\n").unwrap(), "type a = $(self > 1 && 2 > 1)\n");
+    }
+
+    #[test]
+    fn format_preserves_comments() {
+        assert_round_trip("type a = {\n    b: int,\n    // before the closing brace\n}\n");
+        assert_round_trip("type a = int\n    // or string\n    || string\n");
+        assert_round_trip("type a = int // trailing\n");
+        assert_round_trip("use foo::bar // trailing\n");
+        assert_round_trip("type a = {\n    b: int, // trailing\n}\n");
+
+        let formatted = format_source("test.dog", r#"
+            type a = {
+                b: int // no comma
+                // the end
+            }
+            type c = int && // both
+                string
+        "#).unwrap();
+        assert_eq!(formatted, r#"type a = {
+    b: int, // no comma
+    // the end
+}
+
+type c = int
+    // both
+    && string
+"#);
+        assert_round_trip(&formatted);
+    }
+
+    #[test]
+    fn format_never_drops_comments() {
+        for src in [
+            "type a = $(self // c\n > 1)\n",
+            "type a = [ // c\n int]\n",
+            "type a = foo( // c\n int)\n",
+            "#[x]\n// c\ntype a = int\n",
+            "type a = // c\n int\n",
+        ] {
+            assert!(format_source("test.dog", src).is_err(), "{:?}", src);
+        }
+
+        // any comment left unrendered fails, rather than being dropped
+        let unit = PolicyParser::default().parse("test.dog", "type a = int // c\n").unwrap();
+        let comments = vec![Located::new(" c".to_string(), 13..17), Located::new(" lost".to_string(), 20..27)];
+        let errors = Printer::new(comments).print(&unit).unwrap_err();
+        assert_eq!(errors.iter().map(|e| e.span()).collect::<Vec<_>>(), vec![20..27]);
+    }
+
+    #[test]
+    fn fmt_directory_check() {
+        let dir = env::temp_dir().join(format!("seedwing-fmt-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("foo")).unwrap();
+
+        let unformatted = "type   a = {\n  b:int // trailing\n  // the end\n}\n";
+        File::create(dir.join("foo/unformatted.dog")).unwrap().write_all(unformatted.as_bytes()).unwrap();
+        File::create(dir.join("foo/formatted.dog")).unwrap().write_all(b"type a = int\n").unwrap();
+
+        let changed = fmt_directory(&dir, true).unwrap();
+        assert_eq!(changed, vec![dir.join("foo/unformatted.dog")]);
+
+        let mut content = String::new();
+        File::open(&changed[0]).unwrap().read_to_string(&mut content).unwrap();
+        assert_eq!(content, unformatted);
+
+        assert_eq!(fmt_directory(&dir, false).unwrap(), changed);
+        assert!(fmt_directory(&dir, true).unwrap().is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,465 @@
+//! Split policy sources into [`Token`]s before they are handed to the parsers
+//! in [`crate::lang::ty`], [`crate::lang::expr`] and [`crate::lang::attr`].
+
+use crate::lang::{Located, ParserError, ParserInput, Span};
+use chumsky::prelude::*;
+use chumsky::{Parser, Stream};
+use std::fmt::{Display, Formatter};
+
+pub type LexerError = Simple<char>;
+
+pub type TokenStream = Stream<'static, Token, Span, std::vec::IntoIter<(Token, Span)>>;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Keyword {
+    Use,
+    As,
+    Pub,
+    Type,
+    Else,
+    SelfValue,
+    True,
+    False,
+}
+
+impl Keyword {
+    fn from_ident(ident: &str) -> Option<Self> {
+        match ident {
+            "use" => Some(Self::Use),
+            "as" => Some(Self::As),
+            "pub" => Some(Self::Pub),
+            "type" => Some(Self::Type),
+            "else" => Some(Self::Else),
+            "self" => Some(Self::SelfValue),
+            "true" => Some(Self::True),
+            "false" => Some(Self::False),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Keyword {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let keyword = match self {
+            Keyword::Use => "use",
+            Keyword::As => "as",
+            Keyword::Pub => "pub",
+            Keyword::Type => "type",
+            Keyword::Else => "else",
+            Keyword::SelfValue => "self",
+            Keyword::True => "true",
+            Keyword::False => "false",
+        };
+        write!(f, "{}", keyword)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Token {
+    Ident(String),
+    Keyword(Keyword),
+    Integer(String),
+    Decimal(String),
+    Str(String),
+    Op(&'static str),
+    /// Delimiters and separators, along with any character no other token accepts.
+    Punct(char),
+    /// A `//` line comment, without its leading slashes.
+    Comment(String),
+    /// A `//` comment following other tokens on the same line.
+    TrailingComment(String),
+}
+
+impl Display for Token {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Ident(ident) => write!(f, "{}", ident),
+            Token::Keyword(keyword) => write!(f, "{}", keyword),
+            Token::Integer(value) => write!(f, "{}", value),
+            Token::Decimal(value) => write!(f, "{}", value),
+            Token::Str(value) => write!(f, "\"{}\"", value),
+            Token::Op(op) => write!(f, "{}", op),
+            Token::Punct(c) => write!(f, "{}", c),
+            Token::Comment(comment) => write!(f, "//{}", comment),
+            Token::TrailingComment(comment) => write!(f, "//{}", comment),
+        }
+    }
+}
+
+/// Identifiers start with a letter or `_`, and may continue with letters,
+/// digits, `_` and `-`. Within `$( )`, where `-` subtracts, they may not
+/// contain `-`, other than as a field following `.`. Segments of a path,
+/// next to `::`, may also start with a digit or contain `@`.
+pub fn lexer() -> impl Parser<char, (Vec<(Token, Span)>, Span), Error=LexerError> {
+    let comment = just("//")
+        .ignore_then(
+            filter(|c: &char| *c != '\n')
+                .repeated()
+                .collect::<String>()
+        )
+        .map(|comment| Token::Comment(comment.trim_end().to_string()));
+
+    // the fraction may have leading zeros, unlike an integer
+    let fraction = filter(|c: &char| c.is_ascii_digit())
+        .repeated()
+        .at_least(1)
+        .collect::<String>();
+
+    let decimal = text::int(10)
+        .then_ignore(just('.'))
+        .then(fraction)
+        .map(|(integral, decimal)| Token::Decimal(format!("{}.{}", integral, decimal)));
+
+    let integer = text::int(10).map(Token::Integer);
+
+    let string = just('"')
+        .ignore_then(
+            filter(|c: &char| *c != '"')
+                .repeated()
+                .collect::<String>()
+        )
+        .then_ignore(just('"'))
+        .map(Token::Str);
+
+    let op = choice((
+        just("::"),
+        just("&&"),
+        just("||"),
+        just(">="),
+        just("<="),
+        just("=="),
+        just("!="),
+        just(">"),
+        just("<"),
+        just("+"),
+        just("-"),
+        just("*"),
+        just("/"),
+        just("!"),
+        just("="),
+    ))
+        .map(Token::Op);
+
+    let ident = filter(|c: &char| c.is_alphabetic() || *c == '_')
+        .chain(
+            filter(|c: &char| c.is_alphanumeric() || *c == '_' || *c == '-')
+                .repeated()
+        )
+        .collect::<String>()
+        .map(|ident| {
+            if let Some(keyword) = Keyword::from_ident(&ident) {
+                Token::Keyword(keyword)
+            } else {
+                Token::Ident(ident)
+            }
+        });
+
+    let punct = filter(|c: &char| !c.is_whitespace()).map(Token::Punct);
+
+    let trailing = filter(|c: &char| c.is_whitespace() && *c != '\n')
+        .repeated()
+        .ignore_then(comment.map_with_span(|token, span| {
+            match token {
+                Token::Comment(comment) => (Token::TrailingComment(comment), span),
+                token => (token, span),
+            }
+        }));
+
+    let token = comment
+        .or(decimal)
+        .or(integer)
+        .or(string)
+        .or(op)
+        .or(ident)
+        .or(punct);
+
+    text::whitespace()
+        .ignore_then(
+            token
+                .map_with_span(|token, span| (token, span))
+                .then(trailing.or_not())
+                .then_ignore(text::whitespace())
+                .repeated()
+        )
+        .then_ignore(end())
+        .map_with_span(|tokens, span| {
+            let tokens = tokens.into_iter()
+                .flat_map(|(token, trailing)| std::iter::once(token).chain(trailing))
+                .collect();
+            (split_expression_idents(join_path_segments(tokens)), span)
+        })
+}
+
+/// Join adjacent tokens next to `::` back into a single path segment, such
+/// as the `1` and `x` of `foo::1x`, or the `@` and `bar` of `foo::@bar`.
+fn join_path_segments(tokens: Vec<(Token, Span)>) -> Vec<(Token, Span)> {
+    let mut joined: Vec<(Token, Span)> = Vec::with_capacity(tokens.len());
+    let mut tokens = tokens.into_iter().peekable();
+
+    while let Some((token, span)) = tokens.next() {
+        if segment_text(&token).is_none() {
+            joined.push((token, span));
+            continue;
+        }
+
+        let mut run = vec![(token, span)];
+        while let Some((next, next_span)) = tokens.peek() {
+            if next_span.start != run[run.len() - 1].1.end || segment_text(next).is_none() {
+                break;
+            }
+            run.extend(tokens.next());
+        }
+
+        let start = run[0].1.start;
+        let end = run[run.len() - 1].1.end;
+        let after_path = matches!(joined.last(), Some((Token::Op("::"), span)) if span.end == start);
+        let before_path = matches!(tokens.peek(), Some((Token::Op("::"), span)) if span.start == end);
+
+        if after_path || before_path {
+            let segment = run.iter().filter_map(|(token, _)| segment_text(token)).collect();
+            joined.push((Token::Ident(segment), start..end));
+        } else {
+            joined.extend(run);
+        }
+    }
+
+    joined
+}
+
+fn segment_text(token: &Token) -> Option<&str> {
+    match token {
+        Token::Ident(text) | Token::Integer(text) => Some(text),
+        Token::Punct('@') => Some("@"),
+        _ => None,
+    }
+}
+
+/// Split identifiers containing `-` within `$( )` into the terms and
+/// operators of a subtraction, such as `self-1` into `self`, `-` and `1`.
+/// A field accessed with `.` keeps its name whole, so `self.replica-count`
+/// reads the `replica-count` field.
+fn split_expression_idents(tokens: Vec<(Token, Span)>) -> Vec<(Token, Span)> {
+    let mut split = Vec::with_capacity(tokens.len());
+    // parentheses open within the current expression
+    let mut depth = 0;
+
+    for (token, span) in tokens {
+        match &token {
+            Token::Punct('(') if depth > 0 || matches!(split.last(), Some((Token::Punct('$'), _))) => depth += 1,
+            Token::Punct(')') if depth > 0 => depth -= 1,
+            Token::Ident(ident) if depth > 0 && ident.contains('-') && !matches!(split.last(), Some((Token::Punct('.'), _))) => {
+                let mut start = span.start;
+                for (i, term) in ident.split('-').enumerate() {
+                    if i > 0 {
+                        split.push((Token::Op("-"), start - 1..start));
+                    }
+                    if !term.is_empty() {
+                        split.push((term_token(term), start..start + term.len()));
+                    }
+                    start += term.len() + 1;
+                }
+                continue;
+            }
+            _ => {}
+        }
+        split.push((token, span));
+    }
+
+    split
+}
+
+fn term_token(term: &str) -> Token {
+    if let Some(keyword) = Keyword::from_ident(term) {
+        Token::Keyword(keyword)
+    } else if term.chars().all(|c| c.is_ascii_digit()) {
+        Token::Integer(term.into())
+    } else {
+        Token::Ident(term.into())
+    }
+}
+
+/// Lex a source into a stream for the policy parsers, along with any lexing errors.
+pub fn lex<'a, Iter, S>(stream: S) -> (TokenStream, Vec<ParserError>)
+    where
+        Iter: Iterator<Item=(char, Span)> + 'a,
+        S: Into<Stream<'a, char, Span, Iter>>,
+{
+    let (tokens, errors) = lexer().parse_recovery(stream);
+    let (tokens, span) = tokens.unwrap_or_default();
+
+    (
+        Stream::from_iter(span.end..span.end + 1, tokens.into_iter()),
+        errors.into_iter().map(|e| e.map(Token::Punct)).collect(),
+    )
+}
+
+#[allow(clippy::result_large_err)]
+pub fn ident() -> impl Parser<ParserInput, Located<String>, Error=ParserError> + Clone {
+    select! { Token::Ident(ident) => ident }
+        .map_with_span(|ident, span| {
+            Located::new(ident, span)
+        })
+}
+
+/// An identifier, or a keyword where it can only be a name, such as that
+/// of a field or an attribute.
+#[allow(clippy::result_large_err)]
+pub fn name() -> impl Parser<ParserInput, Located<String>, Error=ParserError> + Clone {
+    select! {
+        Token::Ident(ident) => ident,
+        Token::Keyword(keyword) => keyword.to_string(),
+    }
+        .map_with_span(|name, span| {
+            Located::new(name, span)
+        })
+}
+
+pub fn keyword(keyword: Keyword) -> impl Parser<ParserInput, Token, Error=ParserError> + Clone {
+    just(Token::Keyword(keyword))
+}
+
+pub fn op(op: &'static str) -> impl Parser<ParserInput, Token, Error=ParserError> + Clone {
+    just(Token::Op(op))
+}
+
+pub fn punct(c: char) -> impl Parser<ParserInput, Token, Error=ParserError> + Clone {
+    just(Token::Punct(c))
+}
+
+#[cfg(test)]
+pub(crate) fn tokens(src: &str) -> TokenStream {
+    let (tokens, errors) = lex(src);
+    assert!(errors.is_empty(), "{:?}", errors);
+    tokens
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lex_tokens(src: &str) -> Vec<Token> {
+        lexer().parse(src).unwrap().0.into_iter().map(|(token, _)| token).collect()
+    }
+
+    #[test]
+    fn lex_type_definition() {
+        let tokens = lexer().parse("pub type unsigned-int = int && $(self >= 0) // positive\n").unwrap().0;
+
+        assert_eq!(
+            tokens.iter().map(|(token, _)| token.clone()).collect::<Vec<_>>(),
+            vec![
+                Token::Keyword(Keyword::Pub),
+                Token::Keyword(Keyword::Type),
+                Token::Ident("unsigned-int".into()),
+                Token::Op("="),
+                Token::Ident("int".into()),
+                Token::Op("&&"),
+                Token::Punct('$'),
+                Token::Punct('('),
+                Token::Keyword(Keyword::SelfValue),
+                Token::Op(">="),
+                Token::Integer("0".into()),
+                Token::Punct(')'),
+                Token::TrailingComment(" positive".into()),
+            ]
+        );
+        assert_eq!(tokens[2].1, 9..21);
+    }
+
+    #[test]
+    fn lex_subtraction_within_expressions() {
+        assert_eq!(
+            lex_tokens("type a-b = $(self-1 > (x-y)) && a-b"),
+            vec![
+                Token::Keyword(Keyword::Type),
+                Token::Ident("a-b".into()),
+                Token::Op("="),
+                Token::Punct('$'),
+                Token::Punct('('),
+                Token::Keyword(Keyword::SelfValue),
+                Token::Op("-"),
+                Token::Integer("1".into()),
+                Token::Op(">"),
+                Token::Punct('('),
+                Token::Ident("x".into()),
+                Token::Op("-"),
+                Token::Ident("y".into()),
+                Token::Punct(')'),
+                Token::Punct(')'),
+                Token::Op("&&"),
+                Token::Ident("a-b".into()),
+            ]
+        );
+
+        assert_eq!(
+            lex_tokens("$(self.replica-count-1)"),
+            vec![
+                Token::Punct('$'),
+                Token::Punct('('),
+                Token::Keyword(Keyword::SelfValue),
+                Token::Punct('.'),
+                Token::Ident("replica-count-1".into()),
+                Token::Punct(')'),
+            ]
+        );
+
+        let tokens = lexer().parse("$(self-1)").unwrap().0;
+        assert_eq!(tokens.iter().map(|(_, span)| span.clone()).collect::<Vec<_>>(), vec![0..1, 1..2, 2..6, 6..7, 7..8, 8..9]);
+    }
+
+    #[test]
+    fn lex_own_line_and_trailing_comments() {
+        assert_eq!(
+            lex_tokens("// leading\ntype a = int // trailing\n    // own line\n"),
+            vec![
+                Token::Comment(" leading".into()),
+                Token::Keyword(Keyword::Type),
+                Token::Ident("a".into()),
+                Token::Op("="),
+                Token::Ident("int".into()),
+                Token::TrailingComment(" trailing".into()),
+                Token::Comment(" own line".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn lex_path_segments() {
+        assert_eq!(
+            lex_tokens("use @foo::1x::@bar-2 as baz\ntype a = [1]"),
+            vec![
+                Token::Keyword(Keyword::Use),
+                Token::Ident("@foo".into()),
+                Token::Op("::"),
+                Token::Ident("1x".into()),
+                Token::Op("::"),
+                Token::Ident("@bar-2".into()),
+                Token::Keyword(Keyword::As),
+                Token::Ident("baz".into()),
+                Token::Keyword(Keyword::Type),
+                Token::Ident("a".into()),
+                Token::Op("="),
+                Token::Punct('['),
+                Token::Integer("1".into()),
+                Token::Punct(']'),
+            ]
+        );
+    }
+
+    #[test]
+    fn lex_literals_and_paths() {
+        assert_eq!(
+            lex_tokens(r#"::foo::bar 4.2 0.05 "hello world" self-ish"#),
+            vec![
+                Token::Op("::"),
+                Token::Ident("foo".into()),
+                Token::Op("::"),
+                Token::Ident("bar".into()),
+                Token::Decimal("4.2".into()),
+                Token::Decimal("0.05".into()),
+                Token::Str("hello world".into()),
+                Token::Ident("self-ish".into()),
+            ]
+        );
+    }
+}
@@ -1,13 +1,17 @@
 //use crate::lang::expr::expr;
+use crate::lang::lexer::{lex, Token};
 use crate::lang::ty::{compilation_unit, PackagePath, Type, TypeDefn, TypeName};
 use chumsky::prelude::*;
 use chumsky::{Error, Parser, Stream};
-use std::fmt::{Debug, Formatter};
+use std::fmt::{Debug, Display, Formatter};
 use std::hash::{Hash, Hasher};
 use std::ops::{Deref, DerefMut};
 use crate::runtime::BuildError;
 
+pub mod attr;
 pub mod expr;
+pub mod fmt;
+pub mod lexer;
 pub mod ty;
 
 pub type Span = std::ops::Range<usize>;
@@ -18,6 +22,7 @@ pub struct CompilationUnit {
     source: Source,
     uses: Vec<Located<Use>>,
     types: Vec<Located<TypeDefn>>,
+    trailing_comments: Vec<Located<String>>,
 }
 
 impl CompilationUnit {
@@ -26,6 +31,7 @@ impl CompilationUnit {
             source,
             uses: Default::default(),
             types: Default::default(),
+            trailing_comments: Default::default(),
         }
     }
 
@@ -41,6 +47,15 @@ impl CompilationUnit {
         self.types.push(ty)
     }
 
+    /// Comments following the last definition of the unit.
+    pub fn set_trailing_comments(&mut self, comments: Vec<Located<String>>) {
+        self.trailing_comments = comments
+    }
+
+    pub(crate) fn trailing_comments(&self) -> &Vec<Located<String>> {
+        &self.trailing_comments
+    }
+
     pub(crate) fn uses(&self) -> &Vec<Located<Use>> {
         &self.uses
     }
@@ -56,18 +71,40 @@ impl CompilationUnit {
 
 #[derive(Debug)]
 pub struct Use {
+    comments: Vec<Located<String>>,
     type_path: Located<TypeName>,
     as_name: Option<Located<String>>,
+    trailing_comment: Option<Located<String>>,
 }
 
 impl Use {
-    pub fn new(type_path: Located<TypeName>, as_name: Option<Located<String>>) -> Self {
+    pub fn new(comments: Vec<Located<String>>, type_path: Located<TypeName>, as_name: Option<Located<String>>) -> Self {
         Self {
+            comments,
             type_path,
             as_name,
+            trailing_comment: None,
         }
     }
 
+    pub fn comments(&self) -> &Vec<Located<String>> {
+        &self.comments
+    }
+
+    /// A comment on the same line, following the statement.
+    pub fn set_trailing_comment(&mut self, comment: Option<Located<String>>) {
+        self.trailing_comment = comment
+    }
+
+    pub fn trailing_comment(&self) -> Option<&Located<String>> {
+        self.trailing_comment.as_ref()
+    }
+
+    /// The explicit `as` alias, if any.
+    pub fn alias(&self) -> Option<Located<String>> {
+        self.as_name.clone()
+    }
+
     pub fn type_name(&self) -> Located<TypeName> {
         self.type_path.clone()
     }
@@ -198,9 +235,9 @@ impl<T> DerefMut for Located<T> {
 }
 
 #[allow(unused)]
-pub type ParserInput = char;
+pub type ParserInput = Token;
 #[allow(unused)]
-pub type ParserError = Simple<char>;
+pub type ParserError = Simple<Token>;
 
 #[derive(Clone, Debug)]
 pub struct FieldName(String);
@@ -237,6 +274,12 @@ impl From<&str> for Source {
     }
 }
 
+impl Display for Source {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
 impl From<PackagePath> for Source {
     fn from(package: PackagePath) -> Self {
         Source {
@@ -256,11 +299,32 @@ impl PolicyParser {
     pub fn parse<'a, Iter, Src, S>(&self, source: Src, stream: S) -> Result<CompilationUnit, Vec<ParserError>>
         where
             Self: Sized,
-            Iter: Iterator<Item=(ParserInput, <ParserError as Error<ParserInput>>::Span)> + 'a,
+            Iter: Iterator<Item=(char, Span)> + 'a,
+            Src: Into<Source> + Clone,
+            S: Into<Stream<'a, char, Span, Iter>>,
+    {
+        match self.parse_recovering(source, stream) {
+            (Some(unit), errors) if errors.is_empty() => Ok(unit),
+            (_, errors) => Err(errors),
+        }
+    }
+
+    /// Parse as much of the source as possible, returning every independent
+    /// syntax error alongside whatever definitions could still be parsed.
+    ///
+    /// Malformed delimited regions are replaced by placeholders, and malformed
+    /// definitions are skipped up to the next one.
+    pub fn parse_recovering<'a, Iter, Src, S>(&self, source: Src, stream: S) -> (Option<CompilationUnit>, Vec<ParserError>)
+        where
+            Self: Sized,
+            Iter: Iterator<Item=(char, Span)> + 'a,
             Src: Into<Source> + Clone,
-            S: Into<Stream<'a, ParserInput, <ParserError as Error<ParserInput>>::Span, Iter>>,
+            S: Into<Stream<'a, char, Span, Iter>>,
     {
-        Ok(compilation_unit(source).parse(stream)?)
+        let (tokens, mut errors) = lex(stream);
+        let (unit, parser_errors) = compilation_unit(source).parse_recovery(tokens);
+        errors.extend(parser_errors);
+        (unit, errors)
     }
 }
 
@@ -5,6 +5,8 @@ use chumsky::prelude::*;
 use chumsky::Parser;
 use std::fmt::{Debug, Formatter};
 use std::ops::Deref;
+use crate::lang::attr::{attributes, Attribute};
+use crate::lang::lexer::{ident, keyword, name, op, punct, Keyword, Token};
 use crate::lang::expr::{Expr, expr};
 use crate::value::Value;
 
@@ -67,7 +69,6 @@ impl PackagePath {
     }
 
     pub fn type_name(&self, name: String) -> TypeName {
-        println!("TN {:?} -> {}", self.path, name);
         TypeName {
             package: Some(self.clone()),
             name,
@@ -119,7 +120,6 @@ pub struct TypeName {
 
 impl TypeName {
     pub fn new(name: String) -> Self {
-        println!("TN {}", name);
         Self {
             package: None,
             name,
@@ -130,6 +130,10 @@ impl TypeName {
         self.name.clone()
     }
 
+    pub fn package(&self) -> Option<PackagePath> {
+        self.package.clone()
+    }
+
     pub fn is_qualified(&self) -> bool {
         self.package.is_some()
     }
@@ -152,7 +156,6 @@ impl TypeName {
 impl From<String> for TypeName {
     fn from(path: String) -> Self {
         let mut segments = path.split("::").map(|e| e.into()).collect::<Vec<String>>();
-        println!("segments {:?}", segments);
         if segments.is_empty() {
             Self::new("".into())
         } else {
@@ -175,15 +178,52 @@ impl From<String> for TypeName {
 }
 
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Visibility {
+    Public,
+    Private,
+}
+
+impl Visibility {
+    pub fn is_public(&self) -> bool {
+        matches!(self, Visibility::Public)
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct TypeDefn {
+    comments: Vec<Located<String>>,
+    attributes: Vec<Located<Attribute>>,
+    visibility: Visibility,
     name: Located<String>,
     ty: Located<Type>,
+    trailing_comment: Option<Located<String>>,
 }
 
 impl TypeDefn {
-    pub fn new(name: Located<String>, ty: Located<Type>) -> Self {
-        Self { name, ty }
+    pub fn new(comments: Vec<Located<String>>, attributes: Vec<Located<Attribute>>, visibility: Visibility, name: Located<String>, ty: Located<Type>) -> Self {
+        Self { comments, attributes, visibility, name, ty, trailing_comment: None }
+    }
+
+    pub fn comments(&self) -> &Vec<Located<String>> {
+        &self.comments
+    }
+
+    /// A comment on the same line, following the definition.
+    pub fn set_trailing_comment(&mut self, comment: Option<Located<String>>) {
+        self.trailing_comment = comment
+    }
+
+    pub fn trailing_comment(&self) -> Option<&Located<String>> {
+        self.trailing_comment.as_ref()
+    }
+
+    pub fn attributes(&self) -> &Vec<Located<Attribute>> {
+        &self.attributes
+    }
+
+    pub fn visibility(&self) -> Visibility {
+        self.visibility
     }
 
     pub fn name(&self) -> Located<String> {
@@ -198,6 +238,10 @@ impl TypeDefn {
         self.ty.referenced_types()
     }
 
+    pub(crate) fn referenced_functions(&self) -> Vec<Located<TypeName>> {
+        self.ty.referenced_functions()
+    }
+
     pub(crate) fn qualify_types(&mut self, types: &HashMap<String, Option<Located<TypeName>>>) {
         self.ty.qualify_types(types);
     }
@@ -214,6 +258,7 @@ pub enum Type {
     Meet(Box<Located<Type>>, Box<Located<Type>>),
     Functional(Located<TypeName>, Option<Box<Located<Type>>>),
     List(Box<Located<Type>>),
+    Message(Box<Located<Type>>, Located<String>),
     Nothing,
 }
 
@@ -231,10 +276,33 @@ impl Type {
             Type::Meet(lhs, rhs) => lhs.referenced_types().iter().chain(rhs.referenced_types().iter()).cloned().collect(),
             Type::Functional(_, inner) => inner.as_ref().map_or(Vec::default(), |inner| inner.referenced_types()),
             Type::List(inner) => inner.referenced_types(),
+            Type::Message(inner, _) => inner.referenced_types(),
             Type::Nothing => Vec::default(),
         }
     }
 
+    pub(crate) fn referenced_functions(&self) -> Vec<Located<TypeName>> {
+        match self {
+            Type::Functional(fn_name, inner) => {
+                let mut functions = vec![fn_name.clone()];
+                if let Some(inner) = inner {
+                    functions.extend(inner.referenced_functions());
+                }
+                functions
+            }
+            Type::Object(inner) => inner.referenced_functions(),
+            Type::Join(lhs, rhs) => lhs.referenced_functions().iter().chain(rhs.referenced_functions().iter()).cloned().collect(),
+            Type::Meet(lhs, rhs) => lhs.referenced_functions().iter().chain(rhs.referenced_functions().iter()).cloned().collect(),
+            Type::List(inner) => inner.referenced_functions(),
+            Type::Message(inner, _) => inner.referenced_functions(),
+            Type::Anything
+            | Type::Ref(_)
+            | Type::Const(_)
+            | Type::Expr(_)
+            | Type::Nothing => Vec::default(),
+        }
+    }
+
     pub(crate) fn qualify_types(&mut self, types: &HashMap<String, Option<Located<TypeName>>>) {
         match self {
             Type::Anything => {}
@@ -242,8 +310,9 @@ impl Type {
                 if !name.is_qualified() {
                     // it's a simple single-word name, needs qualifying, perhaps.
                     if let Some(Some(qualified)) = types.get(&name.name()) {
-                        println!("replace {:?} with {:?}", name, qualified);
-                        *name = qualified.clone();
+                        tracing::trace!(name = %name.name(), qualified = %qualified.as_type_str(), "qualify reference");
+                        // keep the location of the reference, not of its definition
+                        *name = Located::new(qualified.clone().into_inner(), name.location());
                     }
                 }
             }
@@ -269,6 +338,9 @@ impl Type {
             Type::List(inner) => {
                 inner.qualify_types(types);
             }
+            Type::Message(inner, _) => {
+                inner.qualify_types(types);
+            }
             Type::Nothing => {}
         }
     }
@@ -286,6 +358,7 @@ impl Debug for Type {
             Type::Object(obj) => write!(f, "{:?}", obj),
             Type::Functional(fn_name, ty) => write!(f, "{:?}({:?})", fn_name, ty),
             Type::List(ty) => write!(f, "[{:?}]", ty),
+            Type::Message(ty, message) => write!(f, "{:?} else {:?}", ty, message),
             Type::Expr(expr) => write!(f, "#({:?})", expr)
         }
     }
@@ -294,15 +367,26 @@ impl Debug for Type {
 #[derive(Clone, Debug)]
 pub struct ObjectType {
     fields: Vec<Located<Field>>,
+    trailing_comments: Vec<Located<String>>,
 }
 
 impl ObjectType {
     pub fn new() -> Self {
         Self {
-            fields: vec![]
+            fields: vec![],
+            trailing_comments: vec![],
         }
     }
 
+    /// Comments following the last field, before the closing `}`.
+    pub fn set_trailing_comments(&mut self, comments: Vec<Located<String>>) {
+        self.trailing_comments = comments
+    }
+
+    pub fn trailing_comments(&self) -> &Vec<Located<String>> {
+        &self.trailing_comments
+    }
+
     pub fn add_field(&mut self, field: Located<Field>) -> &Self {
         self.fields.push(field);
         self
@@ -314,6 +398,12 @@ impl ObjectType {
         }).collect()
     }
 
+    pub(crate) fn referenced_functions(&self) -> Vec<Located<TypeName>> {
+        self.fields.iter().flat_map(|e| {
+            e.referenced_functions()
+        }).collect()
+    }
+
     pub(crate) fn qualify_types(&mut self, types: &HashMap<String, Option<Located<TypeName>>>) {
         for field in &mut self.fields {
             field.qualify_types(types);
@@ -327,18 +417,41 @@ impl ObjectType {
 
 #[derive(Clone, Debug)]
 pub struct Field {
+    comments: Vec<Located<String>>,
+    attributes: Vec<Located<Attribute>>,
     name: Located<String>,
     ty: Located<Type>,
+    trailing_comment: Option<Located<String>>,
 }
 
 impl Field {
-    pub fn new(name: Located<String>, ty: Located<Type>) -> Self {
+    pub fn new(comments: Vec<Located<String>>, attributes: Vec<Located<Attribute>>, name: Located<String>, ty: Located<Type>) -> Self {
         Self {
+            comments,
+            attributes,
             name,
             ty,
+            trailing_comment: None,
         }
     }
 
+    pub fn comments(&self) -> &Vec<Located<String>> {
+        &self.comments
+    }
+
+    /// A comment on the same line, following the field.
+    pub fn set_trailing_comment(&mut self, comment: Option<Located<String>>) {
+        self.trailing_comment = comment
+    }
+
+    pub fn trailing_comment(&self) -> Option<&Located<String>> {
+        self.trailing_comment.as_ref()
+    }
+
+    pub fn attributes(&self) -> &Vec<Located<Attribute>> {
+        &self.attributes
+    }
+
     pub fn name(&self) -> &Located<String> {
         &self.name
     }
@@ -351,30 +464,103 @@ impl Field {
         self.ty.referenced_types()
     }
 
+    pub(crate) fn referenced_functions(&self) -> Vec<Located<TypeName>> {
+        self.ty.referenced_functions()
+    }
+
     pub(crate) fn qualify_types(&mut self, types: &HashMap<String, Option<Located<TypeName>>>) {
         self.ty.qualify_types(types)
     }
 }
 
-fn op(op: &str) -> impl Parser<ParserInput, &str, Error=ParserError> + Clone {
-    just(op).padded()
+/// A `//` line comment, without its leading slashes. Where a comment
+/// leads what follows, it may also trail whatever came before it.
+#[allow(clippy::result_large_err)]
+pub fn comment() -> impl Parser<ParserInput, Located<String>, Error=ParserError> + Clone {
+    select! {
+        Token::Comment(comment) => comment,
+        Token::TrailingComment(comment) => comment,
+    }
+        .map_with_span(|comment, span| {
+            Located::new(comment, span)
+        })
+}
+
+/// A `//` comment on the same line as what it follows.
+#[allow(clippy::result_large_err)]
+pub fn trailing_comment() -> impl Parser<ParserInput, Located<String>, Error=ParserError> + Clone {
+    select! { Token::TrailingComment(comment) => comment }
+        .map_with_span(|comment, span| {
+            Located::new(comment, span)
+        })
+}
+
+pub fn comments() -> impl Parser<ParserInput, Vec<Located<String>>, Error=ParserError> + Clone {
+    comment().repeated()
 }
 
 pub fn use_statement() -> impl Parser<ParserInput, Located<Use>, Error=ParserError> + Clone {
-    just("use").padded().ignored()
+    comments()
+        .then_ignore(keyword(Keyword::Use))
         .then(type_name())
         .then(as_clause().or_not())
         // .then( just(";").padded().ignored() )
-        .map_with_span(|(((_, type_path), as_clause)), span| {
+        .map_with_span(|((comments, type_path), as_clause), span| {
             Located::new(
-                Use::new(type_path, as_clause),
+                Use::new(comments, type_path, as_clause),
                 span,
             )
         })
+        .then(trailing_comment().or_not())
+        .map(|(mut use_statement, trailing)| {
+            use_statement.set_trailing_comment(trailing);
+            use_statement
+        })
+}
+
+/// A `use` statement, or nothing if malformed past its `use` keyword, in
+/// which case everything up to the next statement is skipped.
+fn recovering_use_statement() -> impl Parser<ParserInput, Option<Located<Use>>, Error=ParserError> + Clone {
+    let boundary = keyword(Keyword::Use).ignored()
+        .or(keyword(Keyword::Type).ignored())
+        .or(keyword(Keyword::Pub).ignored())
+        .or(punct('#').ignored())
+        .or(comment().ignored())
+        .or(end());
+
+    comments()
+        .then_ignore(keyword(Keyword::Use))
+        .then(
+            type_name()
+                .then(as_clause().or_not())
+                .then_ignore(boundary.rewind())
+                .map(Some)
+                .recover_with(skip_until([
+                    Token::Keyword(Keyword::Use),
+                    Token::Keyword(Keyword::Type),
+                    Token::Keyword(Keyword::Pub),
+                    Token::Punct('#'),
+                ], |_| None))
+        )
+        .map_with_span(|(comments, clause), span| {
+            clause.map(|(type_path, as_clause)| {
+                Located::new(
+                    Use::new(comments, type_path, as_clause),
+                    span,
+                )
+            })
+        })
+        .then(trailing_comment().or_not())
+        .map(|(use_statement, trailing)| {
+            use_statement.map(|mut use_statement| {
+                use_statement.set_trailing_comment(trailing);
+                use_statement
+            })
+        })
 }
 
 pub fn as_clause() -> impl Parser<ParserInput, Located<String>, Error=ParserError> + Clone {
-    just("as").padded().ignored()
+    keyword(Keyword::As).ignored()
         .then(simple_type_name())
         .map(|(_, v)| {
             v
@@ -382,17 +568,7 @@ pub fn as_clause() -> impl Parser<ParserInput, Located<String>, Error=ParserErro
 }
 
 pub fn path_segment() -> impl Parser<ParserInput, Located<String>, Error=ParserError> + Clone {
-    filter(|c: &char| {
-        (c.is_alphanumeric())
-            || *c == '@'
-            || *c == '_'
-            || *c == '-'
-    }).repeated()
-        .collect()
-        .padded()
-        .map_with_span(|v, span|
-            Located::new(v, span)
-        )
+    ident()
 }
 
 pub fn simple_type_name() -> impl Parser<ParserInput, Located<String>, Error=ParserError> + Clone {
@@ -400,12 +576,11 @@ pub fn simple_type_name() -> impl Parser<ParserInput, Located<String>, Error=Par
 }
 
 pub fn type_name() -> impl Parser<ParserInput, Located<TypeName>, Error=ParserError> + Clone {
-    just("::").padded().ignored().or_not()
+    op("::").ignored().or_not()
         .then(
             simple_type_name()
-                .separated_by(just("::"))
+                .separated_by(op("::"))
                 .at_least(1)
-                .allow_leading()
         )
         .map_with_span(|(absolute, mut segments), span| {
             let tail = segments.pop().unwrap();
@@ -434,32 +609,76 @@ pub fn type_name() -> impl Parser<ParserInput, Located<TypeName>, Error=ParserEr
         })
 }
 
+pub fn visibility() -> impl Parser<ParserInput, Visibility, Error=ParserError> + Clone {
+    keyword(Keyword::Pub)
+        .or_not()
+        .map(|v| {
+            if v.is_some() {
+                Visibility::Public
+            } else {
+                Visibility::Private
+            }
+        })
+}
+
 pub fn type_definition() -> impl Parser<ParserInput, Located<TypeDefn>, Error=ParserError> + Clone {
-    just("type")
-        .padded()
-        .ignored()
+    comments()
+        .then(attributes())
+        .then(visibility())
+        .then_ignore(
+            keyword(Keyword::Type)
+        )
         .then(
             simple_type_name()
         )
         .then(
-            just("=")
-                .padded()
+            op("=")
                 .ignored()
                 .then(
-                    type_expr()
+                    with_message(type_expr())
                 )
                 .or_not()
         )
-        .map(|((_, ty_name), ty)| {
+        .then(trailing_comment().or_not())
+        .map(|(((((comments, attributes), visibility), ty_name), ty), trailing)| {
             let ty = ty.unwrap_or({
                 let loc = ty_name.location();
                 ((), Located::new(Type::Nothing, loc.clone()))
             }).1;
 
             let loc = ty_name.span().start()..ty.span().end();
-            Located::new(
-                TypeDefn::new(ty_name, ty),
-                loc)
+            let mut defn = TypeDefn::new(comments, attributes, visibility, ty_name, ty);
+            defn.set_trailing_comment(trailing);
+            Located::new(defn, loc)
+        })
+}
+
+#[allow(clippy::result_large_err)]
+pub fn else_clause() -> impl Parser<ParserInput, Located<String>, Error=ParserError> + Clone {
+    keyword(Keyword::Else)
+        .ignore_then(
+            select! { Token::Str(message) => message }
+                .map_with_span(|message, span| {
+                    Located::new(message, span)
+                })
+        )
+}
+
+/// Allow a type to be followed by `else "message"`, reported when it does not match.
+pub fn with_message(
+    ty: impl Parser<ParserInput, Located<Type>, Error=ParserError> + Clone,
+) -> impl Parser<ParserInput, Located<Type>, Error=ParserError> + Clone {
+    ty.then(else_clause().or_not())
+        .map(|(ty, message)| {
+            if let Some(message) = message {
+                let location = ty.span().start()..message.span().end();
+                Located::new(
+                    Type::Message(Box::new(ty), message),
+                    location,
+                )
+            } else {
+                ty
+            }
         })
 }
 
@@ -475,24 +694,30 @@ pub fn type_expr() -> impl Parser<ParserInput, Located<Type>, Error=ParserError>
 pub fn parenthesized_expr(
     expr: impl Parser<ParserInput, Located<Type>, Error=ParserError> + Clone,
 ) -> impl Parser<ParserInput, Located<Type>, Error=ParserError> + Clone {
-    just("(")
-        .padded()
-        .ignored()
-        .then(expr)
-        .then(just(")").padded().ignored())
-        .map(|((_left_paren, expr), _right_paren)|
-            expr
-        )
+    with_message(expr)
+        .delimited_by(punct('('), punct(')'))
+        .recover_with(nested_delimiters(
+            Token::Punct('('), Token::Punct(')'),
+            [(Token::Punct('['), Token::Punct(']')), (Token::Punct('{'), Token::Punct('}'))],
+            |span| {
+                Located::new(Type::Nothing, span)
+            }))
+}
+
+/// A join or meet operator. Comments around it are not kept in the tree,
+/// but remain in the source for the formatter to find by location.
+fn operator(symbol: &'static str) -> impl Parser<ParserInput, Token, Error=ParserError> + Clone {
+    comments()
+        .ignore_then(op(symbol))
+        .then_ignore(comments())
 }
 
 pub fn logical_or(
     expr: impl Parser<ParserInput, Located<Type>, Error=ParserError> + Clone,
 ) -> impl Parser<ParserInput, Located<Type>, Error=ParserError> + Clone {
     logical_and(expr.clone())
-        .then(op("||").then(expr.clone()).repeated())
+        .then(operator("||").then(expr.clone()).repeated())
         .foldl(|lhs, (_op, rhs)| {
-            println!("lhs {:?}", lhs);
-            println!("rhs {:?}", rhs);
             let location = lhs.span().start()..rhs.span().end();
             Located::new(
                 Type::Join(
@@ -506,7 +731,7 @@ pub fn logical_and(
     expr: impl Parser<ParserInput, Located<Type>, Error=ParserError> + Clone,
 ) -> impl Parser<ParserInput, Located<Type>, Error=ParserError> + Clone {
     ty(expr.clone())
-        .then(op("&&").then(expr.clone()).repeated())
+        .then(operator("&&").then(expr.clone()).repeated())
         .foldl(|lhs, (_op, rhs)| {
             let location = lhs.span().start()..rhs.span().end();
             Located::new(
@@ -518,40 +743,22 @@ pub fn logical_and(
 }
 
 
+#[allow(clippy::result_large_err)]
 pub fn integer_literal() -> impl Parser<ParserInput, Located<Value>, Error=ParserError> + Clone {
-    text::int::<char, ParserError>(10)
-        .padded()
+    select! { Token::Integer(s) => s }
         .map_with_span(|s: String, span| Located::new(s.parse::<i64>().unwrap().into(), span))
 }
 
+#[allow(clippy::result_large_err)]
 pub fn decimal_literal() -> impl Parser<ParserInput, Located<Value>, Error=ParserError> + Clone {
-    text::int(10)
-        .then(just('.').then(text::int(10)))
-        .padded()
-        .map_with_span(
-            |(integral, (_dot, decimal)): (String, (char, String)), span| {
-                Located::new(
-                    format!("{}.{}", integral, decimal).parse::<f64>().unwrap().into(),
-                    span,
-                )
-            },
-        )
+    select! { Token::Decimal(s) => s }
+        .map_with_span(|s: String, span| Located::new(s.parse::<f64>().unwrap().into(), span))
 }
 
+#[allow(clippy::result_large_err)]
 pub fn string_literal() -> impl Parser<ParserInput, Located<Value>, Error=ParserError> + Clone {
-    just('"')
-        .ignored()
-        .then(
-            filter(|c: &char| *c != '"')
-                .repeated()
-                .collect::<String>()
-        )
-        .then(
-            just('"')
-                .ignored()
-        )
-        .padded()
-        .map_with_span(|((_, x), _), span: Span| {
+    select! { Token::Str(x) => x }
+        .map_with_span(|x: String, span: Span| {
             Located::new(
                 x.into(),
                 span.clone(),
@@ -578,15 +785,18 @@ pub fn const_type() -> impl Parser<ParserInput, Located<Type>, Error=ParserError
 }
 
 pub fn expr_ty() -> impl Parser<ParserInput, Located<Type>, Error=ParserError> + Clone {
-    just("$(")
-        .padded()
-        .ignored()
-        .then(expr())
-        .then(
-            just(")")
-                .padded()
-                .ignored())
-        .map_with_span(|((_, expr), y), span| {
+    punct('$')
+        .ignore_then(
+            expr()
+                .delimited_by(punct('('), punct(')'))
+                .recover_with(nested_delimiters(
+                    Token::Punct('('), Token::Punct(')'),
+                    [(Token::Punct('['), Token::Punct(']')), (Token::Punct('{'), Token::Punct('}'))],
+                    |span: Span| {
+                        Located::new(Expr::Value(Located::new(false.into(), span.clone())), span)
+                    }))
+        )
+        .map_with_span(|expr, span| {
             Located::new(
                 Type::Expr(expr),
                 span,
@@ -597,17 +807,17 @@ pub fn expr_ty() -> impl Parser<ParserInput, Located<Type>, Error=ParserError> +
 pub fn functional_ty(expr: impl Parser<ParserInput, Located<Type>, Error=ParserError> + Clone) -> impl Parser<ParserInput, Located<Type>, Error=ParserError> + Clone {
     type_name()
         .then(
-            just("(")
-                .padded()
-                .ignored()
-        )
-        .then(expr.clone().or_not())
-        .then(
-            just(")")
-                .padded()
-                .ignored()
+            with_message(expr.clone())
+                .or_not()
+                .delimited_by(punct('('), punct(')'))
+                .recover_with(nested_delimiters(
+                    Token::Punct('('), Token::Punct(')'),
+                    [(Token::Punct('['), Token::Punct(']')), (Token::Punct('{'), Token::Punct('}'))],
+                    |span| {
+                        Some(Located::new(Type::Nothing, span))
+                    }))
         )
-        .map_with_span(|((((fn_name, _)), ty), _), span| {
+        .map_with_span(|(fn_name, ty), span| {
             let fn_type = Type::Functional(
                 fn_name,
                 ty.map(|ty| Box::new(ty))
@@ -621,16 +831,15 @@ pub fn functional_ty(expr: impl Parser<ParserInput, Located<Type>, Error=ParserE
 }
 
 pub fn list_ty(expr: impl Parser<ParserInput, Located<Type>, Error=ParserError> + Clone) -> impl Parser<ParserInput, Located<Type>, Error=ParserError> + Clone {
-    just("[")
-        .padded()
-        .ignored()
-        .then(expr)
-        .then(
-            just("]")
-                .padded()
-                .ignored()
-        )
-        .map_with_span(|((_, ty), _), span| {
+    with_message(expr)
+        .delimited_by(punct('['), punct(']'))
+        .recover_with(nested_delimiters(
+            Token::Punct('['), Token::Punct(']'),
+            [(Token::Punct('('), Token::Punct(')')), (Token::Punct('{'), Token::Punct('}'))],
+            |span| {
+                Located::new(Type::Nothing, span)
+            }))
+        .map_with_span(|ty, span| {
             Located::new(
                 Type::List(Box::new(ty)),
                 span,
@@ -673,69 +882,83 @@ pub fn type_ref() -> impl Parser<ParserInput, Located<Type>, Error=ParserError>
 }
 
 pub fn object_type(ty: impl Parser<ParserInput, Located<Type>, Error=ParserError> + Clone) -> impl Parser<ParserInput, Located<Type>, Error=ParserError> + Clone {
-    just("{")
-        .padded()
-        .map_with_span(|_, span| {
-            span
-        })
-        .then(
-            field_definition(ty)
-                .separated_by(
-                    just(",")
-                        .padded()
-                        .ignored()
-                )
-                .allow_trailing()
-        )
-        .then(
-            just("}")
-                .padded()
-                .map_with_span(|_, span| {
-                    span
-                })
-        ).map(|((start, fields), end)| {
-        let loc = start.start()..end.end();
-        let mut ty = ObjectType::new();
-        for f in fields {
-            ty.add_field(f);
-        }
+    // a comment after a field's comma trails it, as does one after the
+    // last field when its comma is left out
+    let separator = punct(',')
+        .ignore_then(trailing_comment().or_not())
+        .or(
+            trailing_comment().or_not()
+                .then_ignore(comments().then(punct('}')).rewind())
+        );
 
-        Located::new(
-            Type::Object(ty),
-            loc,
-        )
-    })
+    field_definition(ty)
+        .then(separator)
+        .map(|(mut field, trailing)| {
+            field.set_trailing_comment(trailing);
+            field
+        })
+        .repeated()
+        .then(comments())
+        .delimited_by(punct('{'), punct('}'))
+        .map(|(fields, trailing_comments)| {
+            let mut ty = ObjectType::new();
+            for f in fields {
+                ty.add_field(f);
+            }
+            ty.set_trailing_comments(trailing_comments);
+            ty
+        })
+        .recover_with(nested_delimiters(
+            Token::Punct('{'), Token::Punct('}'),
+            [(Token::Punct('('), Token::Punct(')')), (Token::Punct('['), Token::Punct(']'))],
+            |_| {
+                ObjectType::new()
+            }))
+        .map_with_span(|ty, span| {
+            Located::new(
+                Type::Object(ty),
+                span,
+            )
+        })
 }
 
 pub fn field_name() -> impl Parser<ParserInput, Located<String>, Error=ParserError> + Clone {
-    text::ident().map_with_span(|name, span| {
-        Located::new(name, span)
-    })
+    name()
 }
 
 pub fn field_definition(ty: impl Parser<ParserInput, Located<Type>, Error=ParserError> + Clone) -> impl Parser<ParserInput, Located<Field>, Error=ParserError> + Clone {
-    field_name()
-        .then(just(":").padded().ignored())
-        .then(ty)
-        .map(|((name, _), ty)| {
+    comments()
+        .then(attributes())
+        .then(field_name())
+        .then(punct(':').ignored())
+        .then(with_message(ty))
+        .map(|((((comments, attributes), name), _), ty)| {
             let loc = name.span().start()..ty.span().end();
             Located::new(
-                Field::new(name, ty),
+                Field::new(comments, attributes, name, ty),
                 loc,
             )
         })
 }
 
 pub fn compilation_unit<S: Into<Source> + Clone>(source: S) -> impl Parser<ParserInput, CompilationUnit, Error=ParserError> + Clone {
-    use_statement().padded().repeated()
+    recovering_use_statement()
+        .repeated()
         .then(
-            type_definition().padded().repeated()
+            type_definition()
+                // resynchronize on the next definition
+                .recover_with(skip_then_retry_until::<ParserInput, 0>([]))
+                .repeated()
         )
-        .then_ignore(end())
-        .map(move |(use_statements, types)| {
+        .then(comments())
+        .then_ignore(
+            end().recover_with(skip_until::<ParserInput, _, 0>([], |_| ()))
+        )
+        .map(move |((use_statements, types), trailing_comments)| {
             let mut unit = CompilationUnit::new(source.clone().into());
+            unit.set_trailing_comments(trailing_comments);
 
-            for e in use_statements {
+            for e in use_statements.into_iter().flatten() {
                 unit.add_use(e)
             }
 
@@ -750,25 +973,76 @@ pub fn compilation_unit<S: Into<Source> + Clone>(source: S) -> impl Parser<Parse
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::lang::lexer::tokens;
 
     #[test]
     fn parse_ty_name() {
-        let name = type_name().parse("bob").unwrap().into_inner();
+        let name = type_name().parse(tokens("bob")).unwrap().into_inner();
 
         assert_eq!(name.name(), "bob");
+
+        let name = type_name().then_ignore(end()).parse(tokens("::@foo::1x::bar")).unwrap().into_inner();
+        assert_eq!(name.as_type_str(), "::@foo::1x::bar");
     }
 
     #[test]
     fn parse_ty_defn() {
-        let ty = type_definition().parse("type bob").unwrap().into_inner();
+        let ty = type_definition().parse(tokens("type bob")).unwrap().into_inner();
 
         assert_eq!(&*ty.name.into_inner(), "bob");
+        assert_eq!(ty.visibility, Visibility::Private);
+    }
+
+    #[test]
+    fn parse_ty_defn_attributes() {
+        let ty = type_definition().parse(tokens(r#"
+            #[deprecated("use v2")]
+            #[severity(high)]
+            pub type bob = {
+                #[description("years since birth")]
+                age: int,
+            }
+        "#)).unwrap().into_inner();
+
+        assert_eq!(ty.attributes.len(), 2);
+        assert_eq!(&*ty.attributes[0].name(), "deprecated");
+        assert_eq!(ty.attributes[1].arg(), Some("high".into()));
+
+        if let Type::Object(obj) = &*ty.ty {
+            assert_eq!(&*obj.fields[0].attributes[0].name(), "description");
+        } else {
+            panic!("expected an object type");
+        }
+    }
+
+    #[test]
+    fn parse_reserved_keywords() {
+        assert!(type_definition().then_ignore(end()).parse(tokens("type use = int")).is_err());
+
+        let ty = type_expr().then_ignore(end()).parse(tokens("{ type: string, use: int }")).unwrap().into_inner();
+        if let Type::Object(obj) = ty {
+            let names = obj.fields.iter().map(|e| e.name.clone().into_inner()).collect::<Vec<_>>();
+            assert_eq!(names, vec!["type".to_string(), "use".to_string()]);
+        } else {
+            panic!("expected an object type");
+        }
+
+        let ty = type_definition().parse(tokens("type user-name = string")).unwrap().into_inner();
+        assert_eq!(&*ty.name.into_inner(), "user-name");
+    }
+
+    #[test]
+    fn parse_pub_ty_defn() {
+        let ty = type_definition().parse(tokens("pub type bob")).unwrap().into_inner();
+
+        assert_eq!(&*ty.name.into_inner(), "bob");
+        assert_eq!(ty.visibility, Visibility::Public);
     }
 
     /*
     #[test]
     fn parse_ty_ref() {
-        let ty_ref = type_ref().parse("bob").unwrap().into_inner();
+        let ty_ref = type_ref().parse(tokens("bob")).unwrap().into_inner();
 
         println!("{:?}", ty_ref);
 
@@ -783,12 +1057,12 @@ mod test {
 
     #[test]
     fn parse_simple_obj_ty() {
-        let ty = type_expr().then_ignore(end()).parse(r#"
+        let ty = type_expr().then_ignore(end()).parse(tokens(r#"
             {
                 foo: 81,
                 bar: 4.2,
             }
-        "#).unwrap().into_inner();
+        "#)).unwrap().into_inner();
 
         println!("{:?}", ty);
 
@@ -817,7 +1091,7 @@ mod test {
 
     #[test]
     fn parse_nested_obj_ty() {
-        let ty = type_expr().then_ignore(end()).parse(r#"
+        let ty = type_expr().then_ignore(end()).parse(tokens(r#"
             {
                 foo: 23,
                 bar: {
@@ -825,36 +1099,124 @@ mod test {
                 },
                 taco: int,
             }
-        "#).unwrap().into_inner();
+        "#)).unwrap().into_inner();
 
         println!("{:?}", ty);
     }
 
     #[test]
     fn parse_function_transform() {
-        let ty = type_expr().then_ignore(end()).parse(r#"
+        let ty = type_expr().then_ignore(end()).parse(tokens(r#"
             {
                 name: string && Length( $(self + 1 > 13) ),
             }
-        "#).unwrap().into_inner();
+        "#)).unwrap().into_inner();
 
         println!("{:?}", ty);
     }
 
+    #[test]
+    fn parse_messages() {
+        let ty = type_definition().parse(tokens(r#"
+            type deployment = {
+                replicas: int && $(self > 0) else "replicas must be positive, not {self}",
+            } else "not a valid deployment"
+        "#)).unwrap().into_inner();
+
+        if let Type::Message(inner, message) = &*ty.ty {
+            assert_eq!(&**message, "not a valid deployment");
+            if let Type::Object(obj) = &***inner {
+                assert!(matches!(
+                    &*obj.fields[0].ty,
+                    Type::Message(ty, message)
+                        if matches!(&***ty, Type::Meet(_, _))
+                            && &**message == "replicas must be positive, not {self}"
+                ));
+            } else {
+                panic!("expected an object type");
+            }
+        } else {
+            panic!("expected a message");
+        }
+    }
+
+    #[test]
+    fn parse_recovers_multiple_errors() {
+        let src = r#"
+            type bob = {
+                foo: int
+                bar: int,
+            }
+
+            type jim = $(self > )
+
+            type lily = int
+
+            type taco = [ int && ]
+
+            type quux = && int
+
+            type fred = int
+        "#;
+
+        let (unit, errors) = compilation_unit("test.dog").parse_recovery(tokens(src));
+
+        let unit = unit.unwrap();
+        let names = unit.types().iter().map(|e| e.name().into_inner()).collect::<Vec<_>>();
+        assert!(names.contains(&"lily".to_string()));
+        assert!(names.contains(&"fred".to_string()));
+        assert_eq!(errors.len(), 4);
+
+        let lines = errors.iter()
+            .map(|e| src[..e.span().start].lines().count())
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec![4, 7, 11, 13]);
+    }
+
+    #[test]
+    fn parse_recovers_broken_use() {
+        let src = r#"
+            use 42
+            use x::y::z
+            use ::
+            // a valid type
+            type fred = int
+        "#;
+
+        let (unit, errors) = compilation_unit("test.dog").parse_recovery(tokens(src));
+
+        let unit = unit.unwrap();
+        let uses = unit.uses().iter().map(|e| e.as_name().into_inner()).collect::<Vec<_>>();
+        let names = unit.types().iter().map(|e| e.name().into_inner()).collect::<Vec<_>>();
+        assert_eq!(uses, vec!["z".to_string()]);
+        assert_eq!(names, vec!["fred".to_string()]);
+
+        let lines = errors.iter()
+            .map(|e| src[..e.span().start].lines().count())
+            .collect::<Vec<_>>();
+        assert_eq!(lines, vec![2, 5]);
+
+        let (unit, errors) = compilation_unit("test.dog").parse_recovery(tokens("use foo::\nuse x::y::z\ntype fred = int"));
+        let unit = unit.unwrap();
+        assert_eq!(unit.uses().len(), 1);
+        assert_eq!(unit.types().len(), 1);
+        assert_eq!(errors.len(), 1);
+    }
+
     #[test]
     fn parse_collections() {
-        let ty = type_expr().then_ignore(end()).parse(r#"
+        let ty = type_expr().then_ignore(end()).parse(tokens(r#"
             {
                 name: [int && $(self == 2)]
             }
-        "#).unwrap().into_inner();
+        "#)).unwrap().into_inner();
 
         println!("{:?}", ty);
     }
 
     #[test]
     fn parse_compilation_unit() {
-        let unit = compilation_unit("my_file.dog").parse(r#"
+        let unit = compilation_unit("my_file.dog").parse(tokens(r#"
             use foo::bar::bar
             use x::y::z as osi-approved-license
 
@@ -868,13 +1230,13 @@ mod test {
                 taco: int,
             }
 
-            type jim = int && taco
+            pub type jim = int && taco
 
             type unsigned-int = int && $( self >= 0 )
 
             type lily
 
-        "#).unwrap();
+        "#)).unwrap();
 
         println!("{:?}", unit);
     }
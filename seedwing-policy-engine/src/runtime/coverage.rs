@@ -0,0 +1,350 @@
+//! Which parts of the policies a corpus of evaluations exercised.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::lang::{Located, Source, Span};
+use crate::lang::ty::TypeName;
+use crate::runtime::{PrimordialType, Runtime, RuntimeType};
+use crate::runtime::observer::Observer;
+use crate::value::Value;
+
+/// Something within a policy which evaluation may or may not reach.
+#[derive(Clone, Debug)]
+pub enum Coverable {
+    /// A type definition, reached whenever the type is evaluated.
+    Type(TypeName),
+    /// An object field, reached when the value has the field.
+    Field(String),
+    /// A term of a join, the `term`th of join number `join` in its source.
+    Branch { join: usize, term: usize },
+    Expr,
+}
+
+#[derive(Clone, Debug)]
+pub struct Item {
+    source: Source,
+    span: Span,
+    coverable: Coverable,
+}
+
+impl Item {
+    pub fn source(&self) -> &Source {
+        &self.source
+    }
+
+    pub fn span(&self) -> Span {
+        self.span.clone()
+    }
+
+    pub fn coverable(&self) -> &Coverable {
+        &self.coverable
+    }
+}
+
+/// An [`Observer`] counting how often each [`Coverable`] of a runtime is
+/// reached, over any number of evaluations against it, concurrent or not.
+pub struct Coverage {
+    items: Vec<Item>,
+    hits: Vec<AtomicU64>,
+    // items by the address of the type reaching them, which `nodes` keeps alive
+    index: HashMap<usize, Vec<usize>>,
+    nodes: Vec<Arc<Located<RuntimeType>>>,
+}
+
+impl Coverage {
+    pub fn new(runtime: &Runtime) -> Self {
+        let mut coverage = Self {
+            items: Vec::new(),
+            hits: Vec::new(),
+            index: HashMap::new(),
+            nodes: Vec::new(),
+        };
+
+        let mut types = runtime.types.iter().collect::<Vec<_>>();
+        types.sort_by_key(|(name, _)| name.as_type_str());
+
+        let mut joins = HashMap::new();
+        for (name, defn) in types {
+            // primordials and functions are not written in any source
+            let Some(package) = name.package() else {
+                continue;
+            };
+            if let RuntimeType::Primordial(PrimordialType::Function(..)) = &**defn.ty {
+                continue;
+            }
+            let source = Source::from(package);
+            let joins = joins.entry(source.clone()).or_insert(0);
+            coverage.add(&defn.ty, &source, Coverable::Type(name.clone()));
+            coverage.walk(&defn.ty, &source, joins);
+        }
+
+        coverage
+    }
+
+    fn add(&mut self, ty: &Arc<Located<RuntimeType>>, source: &Source, coverable: Coverable) {
+        self.index.entry(Arc::as_ptr(ty) as usize).or_default().push(self.items.len());
+        self.items.push(Item {
+            source: source.clone(),
+            span: ty.span(),
+            coverable,
+        });
+        self.hits.push(AtomicU64::new(0));
+        self.nodes.push(ty.clone());
+    }
+
+    fn walk(&mut self, ty: &Arc<Located<RuntimeType>>, source: &Source, joins: &mut usize) {
+        match &***ty {
+            RuntimeType::Object(inner) => {
+                for field in &inner.fields {
+                    self.add(&field.ty, source, Coverable::Field((*field.name).clone()));
+                    self.walk(&field.ty, source, joins);
+                }
+            }
+            RuntimeType::Expr(_) => {
+                self.add(ty, source, Coverable::Expr);
+            }
+            RuntimeType::Join(terms) => {
+                let join = *joins;
+                *joins += 1;
+                for (term, ty) in terms.iter().enumerate() {
+                    self.add(ty, source, Coverable::Branch { join, term });
+                    self.walk(ty, source, joins);
+                }
+            }
+            RuntimeType::Meet(terms) => {
+                for ty in terms {
+                    self.walk(ty, source, joins);
+                }
+            }
+            RuntimeType::Functional(_, Some(ty))
+            | RuntimeType::List(ty)
+            | RuntimeType::Message(ty, _) => {
+                self.walk(ty, source, joins);
+            }
+            _ => {}
+        }
+    }
+
+    /// Every item with how often it was reached.
+    pub fn hits(&self) -> impl Iterator<Item=(&Item, u64)> {
+        self.items.iter().zip(self.hits.iter().map(|e| e.load(Ordering::Relaxed)))
+    }
+
+    /// The fraction of items reached at least once.
+    pub fn covered(&self) -> f64 {
+        if self.items.is_empty() {
+            return 1.0;
+        }
+        self.hits().filter(|(_, hits)| *hits > 0).count() as f64 / self.items.len() as f64
+    }
+
+    /// Line, type-definition and branch coverage in the lcov tracefile
+    /// format, for those of `sources` the runtime was built from.
+    pub fn to_lcov(&self, sources: impl IntoIterator<Item=(Source, String)>) -> String {
+        let mut lcov = String::new();
+        for (source, content) in self.sources(sources) {
+            let lines = Lines::new(&content);
+            let hits = self.hits().filter(|(item, _)| item.source == source).collect::<Vec<_>>();
+
+            lcov.push_str(&format!("TN:\nSF:{}.dog\n", source));
+
+            let types = hits.iter()
+                .filter_map(|(item, hits)| match &item.coverable {
+                    Coverable::Type(name) => Some((lines.line(item.span.start), name.as_type_str(), *hits)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            for (line, name, _) in &types {
+                lcov.push_str(&format!("FN:{},{}\n", line, name));
+            }
+            for (_, name, hits) in &types {
+                lcov.push_str(&format!("FNDA:{},{}\n", hits, name));
+            }
+            lcov.push_str(&format!("FNF:{}\nFNH:{}\n", types.len(), types.iter().filter(|e| e.2 > 0).count()));
+
+            let branches = hits.iter()
+                .filter_map(|(item, hits)| match &item.coverable {
+                    Coverable::Branch { join, term } => Some((lines.line(item.span.start), join, term, *hits)),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            for (line, join, term, hits) in &branches {
+                lcov.push_str(&format!("BRDA:{},{},{},{}\n", line, join, term, hits));
+            }
+            lcov.push_str(&format!("BRF:{}\nBRH:{}\n", branches.len(), branches.iter().filter(|e| e.3 > 0).count()));
+
+            let by_line = Self::by_line(&lines, &hits);
+            for (line, hits) in &by_line {
+                lcov.push_str(&format!("DA:{},{}\n", line, hits));
+            }
+            lcov.push_str(&format!("LF:{}\nLH:{}\n", by_line.len(), by_line.values().filter(|e| **e > 0).count()));
+            lcov.push_str("end_of_record\n");
+        }
+        lcov
+    }
+
+    /// A standalone HTML page listing each of `sources` the runtime was
+    /// built from, with reached lines in green and missed lines in red.
+    pub fn to_html(&self, sources: impl IntoIterator<Item=(Source, String)>) -> String {
+        let mut summary = String::new();
+        let mut listings = String::new();
+
+        for (source, content) in self.sources(sources) {
+            let lines = Lines::new(&content);
+            let hits = self.hits().filter(|(item, _)| item.source == source).collect::<Vec<_>>();
+            let by_line = Self::by_line(&lines, &hits);
+            let reached = by_line.values().filter(|e| **e > 0).count();
+
+            summary.push_str(&format!(
+                "<tr><td><a href=\"#{0}\">{0}.dog</a></td><td>{1}/{2}</td><td>{3:.1}%</td></tr>\n",
+                escape(&source.to_string()),
+                reached,
+                by_line.len(),
+                if by_line.is_empty() { 100.0 } else { 100.0 * reached as f64 / by_line.len() as f64 },
+            ));
+
+            listings.push_str(&format!("<h2 id=\"{0}\">{0}.dog</h2>\n<table class=\"source\">\n", escape(&source.to_string())));
+            for (index, text) in content.lines().enumerate() {
+                let line = index + 1;
+                let (class, hits) = match by_line.get(&line) {
+                    Some(0) => ("missed", "0".to_string()),
+                    Some(hits) => ("reached", hits.to_string()),
+                    None => ("", String::new()),
+                };
+                listings.push_str(&format!(
+                    "<tr class=\"{}\"><td class=\"line\">{}</td><td class=\"hits\">{}</td><td><pre>{}</pre></td></tr>\n",
+                    class, line, hits, escape(text),
+                ));
+            }
+            listings.push_str("</table>\n");
+        }
+
+        format!(r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Policy coverage</title>
+<style>
+body {{ font-family: sans-serif; }}
+table.source {{ border-collapse: collapse; }}
+td.line, td.hits {{ text-align: right; padding: 0 0.5em; color: #666; }}
+pre {{ margin: 0; }}
+tr.reached {{ background: #dfd; }}
+tr.missed {{ background: #fdd; }}
+</style>
+</head>
+<body>
+<h1>Policy coverage: {:.1}% of items reached</h1>
+<table>
+<tr><th>Source</th><th>Lines</th><th>Covered</th></tr>
+{}</table>
+{}</body>
+</html>
+"#, 100.0 * self.covered(), summary, listings)
+    }
+
+    /// Those of `sources` holding any items, in order.
+    fn sources(&self, sources: impl IntoIterator<Item=(Source, String)>) -> Vec<(Source, String)> {
+        let mut sources = sources.into_iter()
+            .filter(|(source, _)| self.items.iter().any(|e| e.source == *source))
+            .collect::<Vec<_>>();
+        sources.sort_by_key(|(source, _)| source.to_string());
+        sources
+    }
+
+    /// The most any item starting on each line was reached.
+    fn by_line(lines: &Lines, hits: &[(&Item, u64)]) -> BTreeMap<usize, u64> {
+        let mut by_line = BTreeMap::new();
+        for (item, hits) in hits {
+            let line = by_line.entry(lines.line(item.span.start)).or_insert(0);
+            *line = (*line).max(*hits);
+        }
+        by_line
+    }
+}
+
+impl Observer for Coverage {
+    fn enter(&self, ty: &Arc<Located<RuntimeType>>, _value: &Value, _depth: usize) {
+        if let Some(items) = self.index.get(&(Arc::as_ptr(ty) as usize)) {
+            for item in items {
+                self.hits[*item].fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// Maps character offsets, as found in spans, to line numbers.
+struct Lines {
+    starts: Vec<usize>,
+}
+
+impl Lines {
+    fn new(content: &str) -> Self {
+        let starts = [0].into_iter()
+            .chain(content.chars().enumerate().filter(|(_, c)| *c == '\n').map(|(offset, _)| offset + 1))
+            .collect();
+        Self { starts }
+    }
+
+    /// The 1-based line holding `offset`.
+    fn line(&self, offset: usize) -> usize {
+        match self.starts.binary_search(&offset) {
+            Ok(index) => index + 1,
+            Err(index) => index,
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+    use crate::lang::ty::PackagePath;
+    use crate::runtime::sources::Ephemeral;
+    use crate::runtime::{Builder, EvaluationMode};
+    use super::Coverage;
+
+    #[actix_rt::test]
+    async fn cover_corpus() {
+        let policy = r#"
+pub type pet = {
+    name: $(self != ""),
+    kind: "dog" || "cat",
+} || {
+    species: int,
+}
+"#;
+        let src = || Ephemeral::new(PackagePath::from_parts(vec!["foo", "bar"]), policy.into()).iter();
+
+        let mut builder = Builder::new();
+        builder.build(src()).unwrap();
+        let runtime = builder.link().unwrap();
+
+        let coverage = Coverage::new(&runtime);
+        for each in [json!({ "name": "fido", "kind": "dog" }), json!({ "name": "rex", "kind": "dog" })] {
+            let value = (&each).into();
+            let result = runtime.evaluate_observed("foo::bar::pet".into(), &value, EvaluationMode::Fast, &coverage).await;
+            assert!(result.unwrap().matches());
+        }
+
+        // the type, both objects, three fields, the expression and both kinds
+        assert_eq!(coverage.hits().count(), 9);
+        assert_eq!(coverage.hits().filter(|(_, hits)| *hits > 0).count(), 6);
+
+        let lcov = coverage.to_lcov(src());
+        assert!(lcov.starts_with("TN:\nSF:foo/bar.dog\n"));
+        assert!(lcov.contains("FN:2,::foo::bar::pet\nFNDA:2,::foo::bar::pet\n"));
+        assert!(lcov.contains("BRF:4\nBRH:2\n"));
+        assert!(lcov.contains("DA:3,2\n"));
+        assert!(lcov.contains("DA:6,0\n"));
+        assert!(lcov.ends_with("end_of_record\n"));
+
+        let html = coverage.to_html(src());
+        assert!(html.contains("<tr class=\"missed\"><td class=\"line\">6</td>"));
+        assert!(html.contains("$(self != &quot;&quot;)"));
+    }
+}
@@ -0,0 +1,248 @@
+//! The dependency graph between linked types and functions.
+
+use std::collections::{BTreeMap, BTreeSet};
+use serde_json::json;
+use crate::lang::ty::{TypeName, Visibility};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NodeKind {
+    Type,
+    Function,
+    Primordial,
+}
+
+impl NodeKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            NodeKind::Type => "type",
+            NodeKind::Function => "function",
+            NodeKind::Primordial => "primordial",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Node {
+    name: TypeName,
+    kind: NodeKind,
+    visibility: Visibility,
+}
+
+impl Node {
+    pub fn name(&self) -> &TypeName {
+        &self.name
+    }
+
+    pub fn kind(&self) -> NodeKind {
+        self.kind
+    }
+
+    pub fn visibility(&self) -> Visibility {
+        self.visibility
+    }
+}
+
+/// Which types reference which, keyed by fully-qualified name so that
+/// iteration and exports are in a stable order.
+#[derive(Clone, Debug, Default)]
+pub struct DependencyGraph {
+    nodes: BTreeMap<String, Node>,
+    edges: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl DependencyGraph {
+    pub(crate) fn add_node(&mut self, name: TypeName, kind: NodeKind, visibility: Visibility) {
+        self.edges.entry(name.as_type_str()).or_default();
+        self.nodes.insert(name.as_type_str(), Node { name, kind, visibility });
+    }
+
+    pub(crate) fn add_edge(&mut self, from: &TypeName, to: &TypeName) {
+        self.edges.entry(from.as_type_str()).or_default().insert(to.as_type_str());
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item=&Node> {
+        self.nodes.values()
+    }
+
+    /// The types and functions directly referenced by `path`.
+    pub fn dependencies(&self, path: String) -> Vec<TypeName> {
+        self.edges.get(&TypeName::from(path).as_type_str())
+            .map(|to| self.names(to.iter()))
+            .unwrap_or_default()
+    }
+
+    /// The types directly referencing `path`.
+    pub fn dependents(&self, path: String) -> Vec<TypeName> {
+        let path = TypeName::from(path).as_type_str();
+        self.names(self.dependents_of(&path))
+    }
+
+    /// Every type that directly or transitively references `path`, and so
+    /// may behave differently if it changes.
+    pub fn blast_radius(&self, path: String) -> Vec<TypeName> {
+        self.affected_by(&TypeName::from(path))
+    }
+
+    pub(crate) fn affected_by(&self, path: &TypeName) -> Vec<TypeName> {
+        let path = path.as_type_str();
+        let mut affected = BTreeSet::new();
+        let mut pending = vec![&path];
+
+        while let Some(next) = pending.pop() {
+            for each in self.dependents_of(next) {
+                if *each != path && affected.insert(each) {
+                    pending.push(each);
+                }
+            }
+        }
+
+        self.names(affected.into_iter())
+    }
+
+    /// Every node `path` directly or transitively references.
+    pub(crate) fn reachable_from(&self, path: &TypeName) -> Vec<&Node> {
+        let mut reached = BTreeSet::new();
+        let mut pending = vec![path.as_type_str()];
+
+        while let Some(next) = pending.pop() {
+            for each in self.edges.get(&next).into_iter().flatten() {
+                if reached.insert(each) {
+                    pending.push(each.clone());
+                }
+            }
+        }
+
+        reached.into_iter()
+            .filter_map(|name| self.nodes.get(name))
+            .collect()
+    }
+
+    /// Private types that nothing else references, and so can never be
+    /// evaluated. Public types, functions and primordials may be used from
+    /// outside the graph.
+    pub fn orphans(&self) -> Vec<TypeName> {
+        self.names(
+            self.nodes.iter()
+                .filter(|(_, node)| !node.visibility.is_public())
+                .map(|(name, _)| name)
+                .filter(|name| !self.dependents_of(name).any(|e| e != *name))
+        )
+    }
+
+    /// Render in Graphviz DOT, with public types drawn bold.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph dependencies {\n");
+
+        for (name, node) in &self.nodes {
+            let shape = match node.kind {
+                NodeKind::Type => "box",
+                NodeKind::Function => "ellipse",
+                NodeKind::Primordial => "plaintext",
+            };
+            let style = if node.visibility.is_public() {
+                ", style=bold"
+            } else {
+                ""
+            };
+            dot.push_str(&format!("    \"{}\" [shape={}{}];\n", name, shape, style));
+        }
+
+        for (from, to) in &self.edges {
+            for each in to {
+                dot.push_str(&format!("    \"{}\" -> \"{}\";\n", from, each));
+            }
+        }
+
+        dot.push('}');
+        dot.push('\n');
+        dot
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "nodes": self.nodes.iter()
+                .map(|(name, node)| json!({
+                    "name": name,
+                    "kind": node.kind.as_str(),
+                    "public": node.visibility.is_public(),
+                }))
+                .collect::<Vec<_>>(),
+            "edges": self.edges.iter()
+                .flat_map(|(from, to)| {
+                    to.iter().map(move |each| json!({
+                        "from": from,
+                        "to": each,
+                    }))
+                })
+                .collect::<Vec<_>>(),
+        })
+    }
+
+    fn dependents_of<'g>(&'g self, path: &'g str) -> impl Iterator<Item=&'g String> {
+        self.edges.iter()
+            .filter(move |(_, to)| to.contains(path))
+            .map(|(from, _)| from)
+    }
+
+    fn names<'g>(&self, names: impl Iterator<Item=&'g String>) -> Vec<TypeName> {
+        names
+            .map(|name| {
+                self.nodes.get(name)
+                    .map(|node| node.name.clone())
+                    .unwrap_or_else(|| TypeName::from(name.clone()))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::lang::ty::{PackagePath, TypeName};
+    use crate::runtime::sources::Ephemeral;
+    use crate::runtime::Builder;
+    use serde_json::json;
+
+    fn names(names: Vec<TypeName>) -> Vec<String> {
+        names.iter().map(|e| e.as_type_str()).collect()
+    }
+
+    #[test]
+    fn export_dependency_graph() {
+        let src = Ephemeral::new(PackagePath::from_parts(vec!["foo", "bar"]), r#"
+            type positive = int && $(self > 0)
+            type unused = int
+            pub type replicas = positive
+            pub type deployment = {
+                replicas: replicas,
+                image: base64::Base64(),
+            }
+        "#.into());
+
+        let mut builder = Builder::new();
+        builder.build(src.iter()).unwrap();
+        builder.add_function_package(PackagePath::from_parts(vec!["base64"]), crate::function::base64::package());
+        let runtime = builder.link().unwrap();
+        let graph = runtime.dependencies();
+
+        assert_eq!(names(graph.dependencies("foo::bar::deployment".into())), vec!["::base64::Base64", "::foo::bar::replicas"]);
+        assert_eq!(names(graph.dependents("int".into())), vec!["::foo::bar::positive", "::foo::bar::unused"]);
+        assert_eq!(names(graph.blast_radius("foo::bar::positive".into())), vec!["::foo::bar::deployment", "::foo::bar::replicas"]);
+        assert_eq!(names(graph.orphans()), vec!["::foo::bar::unused"]);
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph dependencies {\n"));
+        assert!(dot.contains("    \"::foo::bar::replicas\" [shape=box, style=bold];\n"));
+        assert!(dot.contains("    \"::foo::bar::replicas\" -> \"::foo::bar::positive\";\n"));
+
+        let json = graph.to_json();
+        assert!(json["nodes"].as_array().unwrap().contains(&json!({
+            "name": "::base64::Base64",
+            "kind": "function",
+            "public": true,
+        })));
+        assert!(json["edges"].as_array().unwrap().contains(&json!({
+            "from": "::foo::bar::positive",
+            "to": "int",
+        })));
+    }
+}
@@ -0,0 +1,79 @@
+//! Atomically replaceable access to the current [`Runtime`] snapshot.
+
+use std::sync::Arc;
+use arc_swap::ArcSwap;
+use crate::runtime::{EvaluationMode, EvaluationResult, Runtime, RuntimeError};
+use crate::runtime::observer::Observer;
+use crate::value::Value;
+
+/// Shares a [`Runtime`] between concurrent evaluations, and lets it be
+/// replaced without waiting for them. An evaluation keeps the snapshot it
+/// started with until it completes.
+pub struct RuntimeHandle {
+    current: ArcSwap<Runtime>,
+}
+
+impl RuntimeHandle {
+    pub fn new(runtime: Arc<Runtime>) -> Self {
+        Self {
+            current: ArcSwap::new(runtime),
+        }
+    }
+
+    /// The current snapshot.
+    pub fn load(&self) -> Arc<Runtime> {
+        self.current.load_full()
+    }
+
+    /// Replace the current snapshot, returning the previous one.
+    pub fn swap(&self, runtime: Arc<Runtime>) -> Arc<Runtime> {
+        self.current.swap(runtime)
+    }
+
+    /// Evaluate a value against a public type of the current snapshot.
+    pub async fn evaluate(&self, path: String, value: &Value) -> Result<EvaluationResult, RuntimeError> {
+        self.load().evaluate(path, value).await
+    }
+
+    pub async fn evaluate_in(&self, path: String, value: &Value, mode: EvaluationMode) -> Result<EvaluationResult, RuntimeError> {
+        self.load().evaluate_in(path, value, mode).await
+    }
+
+    pub async fn evaluate_observed(&self, path: String, value: &Value, mode: EvaluationMode, observer: &dyn Observer) -> Result<EvaluationResult, RuntimeError> {
+        self.load().evaluate_observed(path, value, mode, observer).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::RuntimeHandle;
+    use crate::lang::ty::PackagePath;
+    use crate::runtime::sources::Ephemeral;
+    use crate::runtime::{Builder, Runtime};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    fn link(src: &str) -> Arc<Runtime> {
+        let src = Ephemeral::new(PackagePath::from_parts(vec!["foo", "bar"]), src.into());
+        let mut builder = Builder::new();
+        builder.build(src.iter()).unwrap();
+        builder.link().unwrap()
+    }
+
+    #[actix_rt::test]
+    async fn swap_runtime_snapshots() {
+        let handle = RuntimeHandle::new(link("pub type bob = 1"));
+
+        // as held by an evaluation already in flight
+        let snapshot = handle.load();
+        let previous = handle.swap(link("pub type bob = 2"));
+        assert!(Arc::ptr_eq(&snapshot, &previous));
+
+        let value = (&json!(1)).into();
+        assert!(snapshot.evaluate("foo::bar::bob".into(), &value).await.unwrap().matches());
+        let value = (&json!(1)).into();
+        assert!(!handle.evaluate("foo::bar::bob".into(), &value).await.unwrap().matches());
+        let value = (&json!(2)).into();
+        assert!(handle.evaluate("foo::bar::bob".into(), &value).await.unwrap().matches());
+    }
+}
@@ -0,0 +1,243 @@
+//! Bounds on the resources a single evaluation may use.
+
+use std::future::{poll_fn, Future};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::task::Poll;
+use std::time::{Duration, Instant};
+use futures_timer::Delay;
+use tracing::Instrument;
+use crate::function::Function;
+use crate::lang::ty::TypeName;
+use crate::runtime::RuntimeError;
+use crate::value::Value;
+
+/// How many fields or elements are evaluated at once, unless limited.
+const DEFAULT_CONCURRENCY: usize = 8;
+
+/// Limits applied to each evaluation against a runtime. Only concurrency
+/// is limited by default.
+#[derive(Clone, Debug, Default)]
+pub struct Limits {
+    depth: Option<usize>,
+    fuel: Option<u64>,
+    deadline: Option<Duration>,
+    function_timeout: Option<Duration>,
+    concurrency: Option<usize>,
+}
+
+impl Limits {
+    /// How deeply into a value's fields and elements evaluation may descend.
+    pub fn set_depth(&mut self, depth: usize) {
+        self.depth.replace(depth);
+    }
+
+    /// How many types may be evaluated in total.
+    pub fn set_fuel(&mut self, fuel: u64) {
+        self.fuel.replace(fuel);
+    }
+
+    /// How long an evaluation may take, including any function calls.
+    pub fn set_deadline(&mut self, deadline: Duration) {
+        self.deadline.replace(deadline);
+    }
+
+    /// How long any single function call may take.
+    pub fn set_function_timeout(&mut self, timeout: Duration) {
+        self.function_timeout.replace(timeout);
+    }
+
+    /// How many fields or elements of a value may be evaluated at once,
+    /// when waiting on async functions. Results are the same regardless.
+    pub fn set_concurrency(&mut self, concurrency: usize) {
+        self.concurrency.replace(concurrency);
+    }
+}
+
+/// What remains of the limits during one evaluation.
+pub(crate) struct Budget {
+    limits: Limits,
+    used: AtomicU64,
+    deadline: Option<Instant>,
+}
+
+impl Budget {
+    pub(crate) fn new(limits: &Limits) -> Self {
+        Self {
+            limits: limits.clone(),
+            used: AtomicU64::new(0),
+            deadline: limits.deadline.map(|e| Instant::now() + e),
+        }
+    }
+
+    pub(crate) fn concurrency(&self) -> usize {
+        self.limits.concurrency.unwrap_or(DEFAULT_CONCURRENCY)
+    }
+
+    /// Account for evaluating a type `depth` deep.
+    pub(crate) fn charge(&self, depth: usize) -> Result<(), RuntimeError> {
+        if let Some(max) = self.limits.depth {
+            if depth > max {
+                return Err(RuntimeError::DepthExceeded(max));
+            }
+        }
+        if let Some(fuel) = self.limits.fuel {
+            if self.used.fetch_add(1, Ordering::Relaxed) >= fuel {
+                return Err(RuntimeError::FuelExhausted(fuel));
+            }
+        }
+        self.check_deadline()
+    }
+
+    fn check_deadline(&self) -> Result<(), RuntimeError> {
+        match (self.deadline, self.limits.deadline) {
+            (Some(deadline), Some(limit)) if Instant::now() >= deadline => Err(RuntimeError::DeadlineExceeded(limit)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Call a function, abandoning it once it outlives either the function
+    /// timeout or the evaluation's deadline.
+    pub(crate) async fn call(&self, name: &TypeName, func: &dyn Function, value: &Value) -> Result<Result<Value, ()>, RuntimeError> {
+        let span = tracing::debug_span!("function", name = %name.as_type_str(), outcome = tracing::field::Empty);
+        let result = self.call_within_limits(name, func, value).instrument(span.clone()).await;
+        span.record("outcome", match &result {
+            Ok(Ok(_)) => "ok",
+            Ok(Err(())) => "failed",
+            Err(_) => "error",
+        });
+        result
+    }
+
+    async fn call_within_limits(&self, name: &TypeName, func: &dyn Function, value: &Value) -> Result<Result<Value, ()>, RuntimeError> {
+        self.check_deadline()?;
+
+        let remaining = self.deadline.map(|e| e.saturating_duration_since(Instant::now()));
+        let (timeout, error) = match (self.limits.function_timeout, remaining) {
+            (Some(timeout), Some(remaining)) if timeout < remaining => {
+                (timeout, RuntimeError::FunctionTimeout(name.as_type_str(), timeout))
+            }
+            (_, Some(remaining)) => {
+                (remaining, RuntimeError::DeadlineExceeded(self.limits.deadline.unwrap_or_default()))
+            }
+            (Some(timeout), None) => (timeout, RuntimeError::FunctionTimeout(name.as_type_str(), timeout)),
+            (None, None) => return Ok(func.call(value).await),
+        };
+
+        let mut call = func.call(value);
+        let mut delay = Delay::new(timeout);
+        let mut error = Some(error);
+        poll_fn(|cx| {
+            if let Poll::Ready(output) = call.as_mut().poll(cx) {
+                return Poll::Ready(Ok(output));
+            }
+            match Pin::new(&mut delay).poll(cx) {
+                Poll::Ready(()) => Poll::Ready(Err(error.take().unwrap())),
+                Poll::Pending => Poll::Pending,
+            }
+        }).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::time::Duration;
+    use serde_json::json;
+    use crate::function::{Function, FunctionPackage};
+    use crate::lang::ty::PackagePath;
+    use crate::runtime::sources::Ephemeral;
+    use crate::runtime::{Builder, Runtime, RuntimeError};
+    use crate::value::Value;
+    use std::sync::Arc;
+    use super::Limits;
+
+    /// Never completes.
+    #[derive(Debug)]
+    struct Hang;
+
+    impl Function for Hang {
+        fn call<'v>(&'v self, _input: &'v Value) -> Pin<Box<dyn Future<Output=Result<Value, ()>> + Send + 'v>> {
+            Box::pin(std::future::pending())
+        }
+    }
+
+    fn link(limits: Limits) -> Arc<Runtime> {
+        let src = Ephemeral::new(PackagePath::from_parts(vec!["foo", "bar"]), r#"
+            pub type chain = int || { next: chain }
+            pub type numbers = [int]
+            pub type hangs = test::Hang()
+        "#.into());
+
+        let mut test = FunctionPackage::new();
+        test.register("Hang".into(), Hang);
+
+        let mut builder = Builder::new();
+        builder.add_function_package(PackagePath::from_parts(vec!["test"]), test);
+        builder.set_limits(limits);
+        builder.build(src.iter()).unwrap();
+        builder.link().unwrap()
+    }
+
+    #[test]
+    fn limit_depth_and_fuel() {
+        let mut limits = Limits::default();
+        limits.set_depth(8);
+        let runtime = link(limits);
+
+        let shallow = (&json!({ "next": { "next": 1 } })).into();
+        assert!(runtime.evaluate_blocking("foo::bar::chain".into(), &shallow).unwrap().matches());
+
+        let deep = (0..10).fold(json!(1), |next, _| json!({ "next": next }));
+        let deep = (&deep).into();
+        assert!(matches!(
+            runtime.evaluate_blocking("foo::bar::chain".into(), &deep),
+            Err(RuntimeError::DepthExceeded(8))
+        ));
+
+        let mut limits = Limits::default();
+        limits.set_fuel(100);
+        let runtime = link(limits);
+
+        let short = (&json!((0..10).collect::<Vec<_>>())).into();
+        assert!(runtime.evaluate_blocking("foo::bar::numbers".into(), &short).unwrap().matches());
+        assert!(runtime.compile().evaluate_blocking("foo::bar::numbers".into(), &short).unwrap().matches());
+
+        let long = (&json!((0..1000).collect::<Vec<_>>())).into();
+        assert!(matches!(
+            runtime.evaluate_blocking("foo::bar::numbers".into(), &long),
+            Err(RuntimeError::FuelExhausted(100))
+        ));
+        assert!(matches!(
+            runtime.compile().evaluate_blocking("foo::bar::numbers".into(), &long),
+            Err(RuntimeError::FuelExhausted(100))
+        ));
+    }
+
+    #[actix_rt::test]
+    async fn limit_time() {
+        let value = (&json!(1)).into();
+
+        let mut limits = Limits::default();
+        limits.set_function_timeout(Duration::from_millis(10));
+        let runtime = link(limits);
+        assert!(matches!(
+            runtime.evaluate("foo::bar::hangs".into(), &value).await,
+            Err(RuntimeError::FunctionTimeout(name, _)) if name == "::test::Hang"
+        ));
+
+        let mut limits = Limits::default();
+        limits.set_deadline(Duration::from_millis(10));
+        limits.set_function_timeout(Duration::from_secs(60));
+        let runtime = link(limits);
+        assert!(matches!(
+            runtime.evaluate("foo::bar::hangs".into(), &value).await,
+            Err(RuntimeError::DeadlineExceeded(_))
+        ));
+        assert!(matches!(
+            runtime.compile().evaluate("foo::bar::hangs".into(), &value).await,
+            Err(RuntimeError::DeadlineExceeded(_))
+        ));
+    }
+}
@@ -0,0 +1,143 @@
+//! Detection of recursive definitions that never descend into the value
+//! being evaluated, and so would recurse forever.
+
+use std::collections::{HashMap, HashSet};
+use crate::lang::ty::{Type, TypeName};
+use crate::lang::{Located, Source};
+use crate::runtime::BuildError;
+
+pub(crate) struct CycleChecker<'t> {
+    source: Source,
+    types: &'t HashMap<TypeName, &'t Located<Type>>,
+    errors: Vec<BuildError>,
+}
+
+impl<'t> CycleChecker<'t> {
+    pub(crate) fn new(source: Source, types: &'t HashMap<TypeName, &'t Located<Type>>) -> Self {
+        Self {
+            source,
+            types,
+            errors: Vec::new(),
+        }
+    }
+
+    pub(crate) fn into_errors(self) -> Vec<BuildError> {
+        self.errors
+    }
+
+    /// Report the first reference within `defn` that leads back to it
+    /// without passing through an object field or list element.
+    pub(crate) fn check(&mut self, defn: &TypeName, ty: &Located<Type>) {
+        let mut refs = Vec::new();
+        unguarded_refs(ty, &mut refs);
+
+        let mut visited = HashSet::new();
+        for each in refs {
+            if self.reaches(each, defn, &mut visited) {
+                self.errors.push(BuildError::NonProductiveCycle(self.source.clone(), each.clone()));
+                return;
+            }
+        }
+    }
+
+    fn reaches(&self, from: &TypeName, target: &TypeName, visited: &mut HashSet<TypeName>) -> bool {
+        if from == target {
+            return true;
+        }
+        if !visited.insert(from.clone()) {
+            return false;
+        }
+
+        if let Some(ty) = self.types.get(from) {
+            let mut refs = Vec::new();
+            unguarded_refs(ty, &mut refs);
+            refs.iter().any(|each| self.reaches(each, target, visited))
+        } else {
+            false
+        }
+    }
+}
+
+/// References evaluated against the same value as `ty` itself.
+fn unguarded_refs<'t>(ty: &'t Located<Type>, refs: &mut Vec<&'t Located<TypeName>>) {
+    match &**ty {
+        Type::Ref(name) => refs.push(name),
+        Type::Meet(lhs, rhs) | Type::Join(lhs, rhs) => {
+            unguarded_refs(lhs, refs);
+            unguarded_refs(rhs, refs);
+        }
+        Type::Message(inner, _) => unguarded_refs(inner, refs),
+        // a function may hand back the very value it was given
+        Type::Functional(_, Some(inner)) => unguarded_refs(inner, refs),
+        Type::Object(_)
+        | Type::List(_)
+        | Type::Functional(_, None)
+        | Type::Expr(_)
+        | Type::Const(_)
+        | Type::Anything
+        | Type::Nothing => {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::lang::ty::PackagePath;
+    use crate::runtime::sources::Ephemeral;
+    use crate::runtime::{BuildError, Builder, Runtime};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    fn link(src: &str) -> Result<Arc<Runtime>, Vec<BuildError>> {
+        let src = Ephemeral::new(PackagePath::from_parts(vec!["foo", "bar"]), src.into());
+        let mut builder = Builder::new();
+        builder.build(src.iter()).unwrap();
+        builder.link()
+    }
+
+    #[actix_rt::test]
+    async fn evaluate_recursive_type() {
+        let runtime = link(r#"
+            pub type tree = {
+                value: int,
+                children: [tree],
+            }
+            pub type chain = int || { next: chain }
+        "#).unwrap();
+
+        let value = (&json!({
+            "value": 1,
+            "children": [
+                { "value": 2, "children": [] },
+                { "value": 3, "children": [ { "value": 4, "children": [] } ] },
+            ]
+        })).into();
+        assert!(runtime.evaluate("foo::bar::tree".into(), &value).await.unwrap().matches());
+
+        let value = (&json!({
+            "value": 1,
+            "children": [ { "value": "two", "children": [] } ]
+        })).into();
+        assert!(!runtime.evaluate("foo::bar::tree".into(), &value).await.unwrap().matches());
+
+        let value = (&json!({ "next": { "next": 42 } })).into();
+        assert!(runtime.evaluate("foo::bar::chain".into(), &value).await.unwrap().matches());
+    }
+
+    #[test]
+    fn reject_non_productive_cycles() {
+        let errors = link(r#"
+            pub type a = b
+            type b = int && a
+            pub type c = c || int
+        "#).err().unwrap();
+
+        assert!(matches!(
+            errors.as_slice(),
+            [
+                BuildError::NonProductiveCycle(_, a),
+                BuildError::NonProductiveCycle(_, b),
+                BuildError::NonProductiveCycle(_, c),
+            ] if a.name() == "b" && b.name() == "a" && c.span() == (83..84)
+        ));
+    }
+}
@@ -0,0 +1,317 @@
+//! Lints reported while linking, each reported at a configurable [`LintLevel`].
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
+use crate::lang::ty::{PackagePath, Type, TypeName};
+use crate::lang::{CompilationUnit, Located, Location};
+use crate::runtime::BuildWarning;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Lint {
+    /// A `use` whose type is never referenced.
+    UnusedUse,
+    /// A private type that no other type references.
+    UnusedType,
+    /// A `use` whose name is hidden by a type defined alongside it.
+    ShadowedUse,
+    /// A branch of a join that no value can ever match.
+    UnreachableBranch,
+}
+
+impl Display for Lint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Lint::UnusedUse => "unused-use",
+            Lint::UnusedType => "unused-type",
+            Lint::ShadowedUse => "shadowed-use",
+            Lint::UnreachableBranch => "unreachable-branch",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LintLevel {
+    Allow,
+    Warn,
+    Deny,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct LintConfig {
+    levels: HashMap<Lint, LintLevel>,
+    deny_warnings: bool,
+}
+
+impl LintConfig {
+    pub fn set_level(&mut self, lint: Lint, level: LintLevel) {
+        self.levels.insert(lint, level);
+    }
+
+    /// Every lint warns unless configured otherwise.
+    pub fn level(&self, lint: Lint) -> LintLevel {
+        self.levels.get(&lint).copied().unwrap_or(LintLevel::Warn)
+    }
+
+    /// Fail linking on any warning, not only on denied lints.
+    pub fn set_deny_warnings(&mut self, deny_warnings: bool) {
+        self.deny_warnings = deny_warnings;
+    }
+
+    pub fn deny_warnings(&self) -> bool {
+        self.deny_warnings
+    }
+}
+
+pub(crate) struct Linter<'t> {
+    types: &'t HashMap<TypeName, &'t Located<Type>>,
+    warnings: Vec<BuildWarning>,
+}
+
+impl<'t> Linter<'t> {
+    pub(crate) fn new(types: &'t HashMap<TypeName, &'t Located<Type>>) -> Self {
+        Self {
+            types,
+            warnings: Vec::new(),
+        }
+    }
+
+    pub(crate) fn into_warnings(self) -> Vec<BuildWarning> {
+        self.warnings
+    }
+
+    pub(crate) fn lint(&mut self, units: &[&CompilationUnit]) {
+        for unit in units {
+            let unit_path = PackagePath::from(unit.source());
+
+            // a `use` only serves its own unit, and private types are only
+            // visible within it, so only its own references count
+            let referenced = unit.types().iter()
+                .flat_map(|defn| {
+                    let defn_name = unit_path.type_name(defn.name().clone().into_inner());
+                    defn.referenced_types().into_iter()
+                        .chain(defn.referenced_functions())
+                        .map(|e| e.into_inner())
+                        .filter(move |e| *e != defn_name)
+                })
+                .collect::<HashSet<TypeName>>();
+
+            for each in unit.uses() {
+                let as_name = each.as_name();
+                if unit.types().iter().any(|defn| *defn.name() == *as_name) {
+                    self.warnings.push(BuildWarning::Lint(Lint::ShadowedUse, unit.source(), as_name.location()));
+                } else if !referenced.contains(&each.type_name()) {
+                    self.warnings.push(BuildWarning::Lint(Lint::UnusedUse, unit.source(), each.location()));
+                }
+            }
+
+            for defn in unit.types() {
+                let defn_name = unit_path.type_name(defn.name().clone().into_inner());
+                if !defn.visibility().is_public() && !referenced.contains(&defn_name) {
+                    self.warnings.push(BuildWarning::Lint(Lint::UnusedType, unit.source(), defn.name().location()));
+                }
+
+                let mut unreachable = Vec::new();
+                self.unreachable_branches(defn.ty(), &mut unreachable);
+                self.warnings.extend(
+                    unreachable.into_iter()
+                        .map(|location| BuildWarning::Lint(Lint::UnreachableBranch, unit.source(), location))
+                );
+            }
+        }
+    }
+
+    fn unreachable_branches(&self, ty: &Located<Type>, unreachable: &mut Vec<Location>) {
+        match &**ty {
+            Type::Join(_, _) => {
+                let mut terms = Vec::new();
+                join_terms(ty, &mut terms);
+                for term in terms {
+                    if self.never_matches(term, &mut HashSet::new()) {
+                        unreachable.push(term.location());
+                    } else {
+                        self.unreachable_branches(term, unreachable);
+                    }
+                }
+            }
+            Type::Meet(lhs, rhs) => {
+                self.unreachable_branches(lhs, unreachable);
+                self.unreachable_branches(rhs, unreachable);
+            }
+            Type::Object(obj) => {
+                for field in obj.fields() {
+                    self.unreachable_branches(field.ty(), unreachable);
+                }
+            }
+            Type::List(inner)
+            | Type::Message(inner, _)
+            | Type::Functional(_, Some(inner)) => self.unreachable_branches(inner, unreachable),
+            Type::Functional(_, None)
+            | Type::Ref(_)
+            | Type::Const(_)
+            | Type::Expr(_)
+            | Type::Anything
+            | Type::Nothing => {}
+        }
+    }
+
+    fn never_matches(&self, ty: &Located<Type>, visited: &mut HashSet<TypeName>) -> bool {
+        match &**ty {
+            Type::Nothing => true,
+            Type::Expr(expr) => {
+                let expr = expr.simplify_expr();
+                matches!(
+                    expr.as_ref().ok().and_then(|e| e.try_get_constant()).and_then(|v| v.try_get_boolean()),
+                    Some(false)
+                )
+            }
+            Type::Meet(lhs, rhs) => {
+                if let (Type::Const(l), Type::Const(r)) = (&***lhs, &***rhs) {
+                    l != r
+                } else {
+                    self.never_matches(lhs, visited) || self.never_matches(rhs, visited)
+                }
+            }
+            Type::Join(lhs, rhs) => {
+                self.never_matches(lhs, visited) && self.never_matches(rhs, visited)
+            }
+            Type::Message(inner, _) => self.never_matches(inner, visited),
+            Type::Ref(name) => {
+                if !visited.insert(name.clone().into_inner()) {
+                    return false;
+                }
+                self.types.get(&**name)
+                    .is_some_and(|ty| self.never_matches(ty, visited))
+            }
+            Type::Object(_)
+            | Type::List(_)
+            | Type::Functional(_, _)
+            | Type::Const(_)
+            | Type::Anything => false,
+        }
+    }
+}
+
+fn join_terms<'t>(ty: &'t Located<Type>, terms: &mut Vec<&'t Located<Type>>) {
+    if let Type::Join(lhs, rhs) = &**ty {
+        join_terms(lhs, terms);
+        join_terms(rhs, terms);
+    } else {
+        terms.push(ty);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Lint, LintLevel};
+    use crate::lang::ty::PackagePath;
+    use crate::runtime::sources::Ephemeral;
+    use crate::runtime::{BuildError, BuildWarning, Builder};
+
+    const SRC: &str = r#"
+        use foo::baz::jim
+        use foo::baz::bob
+        use foo::baz::jim as anne
+
+        type unused = int
+        type anne = int
+        pub type bob = int || (1 && 2) || $(1 > 2)
+    "#;
+
+    fn sources() -> Builder {
+        let bar = Ephemeral::new(PackagePath::from_parts(vec!["foo", "bar"]), SRC.into());
+        let baz = Ephemeral::new(PackagePath::from_parts(vec!["foo", "baz"]), r#"
+            pub type jim = int
+            pub type bob = int
+        "#.into());
+
+        let mut builder = Builder::new();
+        builder.build(bar.iter()).unwrap();
+        builder.build(baz.iter()).unwrap();
+        builder
+    }
+
+    fn lints(warnings: &[BuildWarning]) -> Vec<(Lint, &str)> {
+        warnings.iter()
+            .filter_map(|e| match e {
+                BuildWarning::Lint(lint, _, location) => Some((*lint, &SRC[location.span()])),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn report_lints() {
+        let runtime = sources().link().unwrap();
+
+        assert_eq!(
+            lints(runtime.warnings()),
+            vec![
+                (Lint::UnusedUse, "use foo::baz::jim"),
+                (Lint::ShadowedUse, "foo::baz::bob"),
+                (Lint::ShadowedUse, "anne"),
+                (Lint::UnusedType, "unused"),
+                (Lint::UnusedType, "anne"),
+                (Lint::UnreachableBranch, "1 && 2"),
+                (Lint::UnreachableBranch, "$(1 > 2)"),
+            ]
+        );
+    }
+
+    #[test]
+    fn report_unused_use_per_unit() {
+        let common = Ephemeral::new(PackagePath::from_parts(vec!["foo", "common"]), r#"
+            pub type x = int
+        "#.into());
+        let unused = Ephemeral::new(PackagePath::from_parts(vec!["foo", "unused"]), r#"
+            use foo::common::x
+            pub type a = int
+        "#.into());
+        let used = Ephemeral::new(PackagePath::from_parts(vec!["foo", "used"]), r#"
+            use foo::common::x
+            pub type b = x
+        "#.into());
+
+        let mut builder = Builder::new();
+        builder.build(common.iter()).unwrap();
+        builder.build(unused.iter()).unwrap();
+        builder.build(used.iter()).unwrap();
+        let runtime = builder.link().unwrap();
+
+        let unused_uses = runtime.warnings().iter()
+            .filter_map(|e| match e {
+                BuildWarning::Lint(Lint::UnusedUse, source, _) => Some(source.to_string()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(unused_uses, vec!["foo/unused".to_string()]);
+    }
+
+    #[test]
+    fn configure_lint_levels() {
+        let mut builder = sources();
+        builder.set_lint_level(Lint::UnusedType, LintLevel::Allow);
+        builder.set_lint_level(Lint::UnusedUse, LintLevel::Deny);
+        let errors = builder.link().err().unwrap();
+
+        assert!(matches!(
+            errors.as_slice(),
+            [BuildError::Denied(BuildWarning::Lint(Lint::UnusedUse, _, _))]
+        ));
+
+        let mut builder = sources();
+        builder.set_lint_level(Lint::UnusedUse, LintLevel::Allow);
+        builder.set_lint_level(Lint::ShadowedUse, LintLevel::Allow);
+        builder.set_lint_level(Lint::UnusedType, LintLevel::Allow);
+        builder.set_lint_level(Lint::UnreachableBranch, LintLevel::Allow);
+        let runtime = builder.link().unwrap();
+        assert!(lints(runtime.warnings()).is_empty());
+
+        let mut builder = sources();
+        builder.set_lint_level(Lint::UnreachableBranch, LintLevel::Allow);
+        builder.set_deny_warnings(true);
+        let errors = builder.link().err().unwrap();
+        assert!(errors.iter().all(|e| matches!(e, BuildError::Denied(_))));
+        assert!(!errors.iter().any(|e| matches!(e, BuildError::Denied(BuildWarning::Lint(Lint::UnreachableBranch, _, _)))));
+    }
+}
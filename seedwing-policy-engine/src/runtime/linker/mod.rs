@@ -2,26 +2,69 @@ use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 use std::sync::Arc;
 use crate::function::FunctionPackage;
-use crate::lang::{CompilationUnit, Located};
-use crate::lang::ty::{PackagePath, Type, TypeName};
-use crate::runtime::{BuildError, Runtime, RuntimeType};
+use crate::function::memo::FunctionCache;
+use crate::lang::{CompilationUnit, Located, Source};
+use crate::lang::ty::{PackagePath, Type, TypeName, Visibility};
+use crate::lang::attr::{Attribute, find_attribute};
+use crate::runtime::{BuildError, BuildWarning, Runtime, RuntimeType};
+use crate::runtime::graph::{DependencyGraph, NodeKind};
+use crate::runtime::limits::Limits;
+use crate::runtime::linker::cycles::CycleChecker;
+use crate::runtime::linker::lint::{LintConfig, LintLevel, Linter};
+use crate::runtime::linker::simplify::Simplifier;
+use crate::runtime::linker::typecheck::{ExprType, TypeChecker};
 
-pub struct Linker {
-    units: Vec<CompilationUnit>,
-    packages: HashMap<PackagePath, FunctionPackage>,
+mod cycles;
+pub mod lint;
+mod simplify;
+pub mod typecheck;
+
+pub struct Linker<'l> {
+    units: &'l mut Vec<CompilationUnit>,
+    packages: &'l HashMap<PackagePath, FunctionPackage>,
+    lints: &'l LintConfig,
+    limits: &'l Limits,
+    cache: Option<&'l Arc<FunctionCache>>,
 }
 
-impl Linker {
-    pub fn new(units: Vec<CompilationUnit>, packages: HashMap<PackagePath, FunctionPackage>) -> Self {
+impl<'l> Linker<'l> {
+    pub fn new(units: &'l mut Vec<CompilationUnit>, packages: &'l HashMap<PackagePath, FunctionPackage>, lints: &'l LintConfig, limits: &'l Limits, cache: Option<&'l Arc<FunctionCache>>) -> Self {
         Self {
             units,
             packages,
+            lints,
+            limits,
+            cache,
         }
     }
 
-    pub fn link(mut self) -> Result<Arc<Runtime>, Vec<BuildError>> {
+    pub fn link(self) -> Result<Arc<Runtime>, Vec<BuildError>> {
+        self.link_with(None)
+    }
+
+    /// Link against a previous runtime, which already holds the units not
+    /// within `changed`. Only types defined by changed units, and those
+    /// depending on them, are converted afresh; the rest are shared.
+    pub fn relink(self, previous: &Runtime, changed: &HashSet<Source>) -> Result<Arc<Runtime>, Vec<BuildError>> {
+        self.link_with(Some((previous, changed)))
+    }
+
+    fn link_with(self, previous: Option<(&Runtime, &HashSet<Source>)>) -> Result<Arc<Runtime>, Vec<BuildError>> {
+        let _link = tracing::debug_span!("link", units = self.units.len(), relink = previous.is_some()).entered();
+
+        let mut errors = Vec::new();
+        let mut warnings = Vec::new();
+
         // First, perform internal per-unit linkage and type qualification
-        for mut unit in &mut self.units {
+        let phase = tracing::debug_span!("qualify").entered();
+        for unit in self.units.iter_mut() {
+            if let Some((_, changed)) = previous {
+                // unchanged units were qualified when previously linked
+                if !changed.contains(&unit.source()) {
+                    continue;
+                }
+            }
+
             let unit_path = PackagePath::from(unit.source());
 
             let mut visible_types = unit
@@ -60,26 +103,30 @@ impl Linker {
             }
 
             for defn in unit.types() {
-                println!("defn {:?}", defn);
                 let referenced_types = defn.referenced_types();
 
                 for ty in &referenced_types {
                     if !ty.is_qualified() {
                         if !visible_types.contains_key(&ty.name()) {
-                            todo!("unknown type referenced {:?}", ty)
+                            errors.push(BuildError::TypeNotFound(unit.source(), ty.clone()));
                         }
                     }
                 }
             }
 
-            println!("qualify with {:?}", visible_types);
-
             for defn in unit.types_mut() {
                 defn.qualify_types(&visible_types)
             }
         }
 
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        drop(phase);
+
         // next, perform inter-unit linking.
+        let phase = tracing::debug_span!("resolve").entered();
 
         let mut world = Vec::new();
 
@@ -89,7 +136,7 @@ impl Linker {
 
         //world.push("int".into());
 
-        for (path, package) in &self.packages {
+        for (path, package) in self.packages.iter() {
             let package_path = path;
 
             world.extend_from_slice(
@@ -98,13 +145,10 @@ impl Linker {
                     package_path.type_name(e.clone())
                 }).collect::<Vec<TypeName>>()
             );
-
-            println!("{:?}", world);
         }
 
-        for unit in &self.units {
+        for unit in self.units.iter() {
             let unit_path = PackagePath::from(unit.source());
-            println!("@@@@ {:?}", unit_path);
 
             let unit_types = unit.types().iter()
                 .map(|e| {
@@ -115,47 +159,275 @@ impl Linker {
             world.extend_from_slice(&unit_types);
         }
 
-        println!("world {:?}", world);
-        for unit in &self.units {
+        let visibility = self.units.iter()
+            .flat_map(|unit| {
+                let unit_path = PackagePath::from(unit.source());
+                unit.types().iter()
+                    .map(move |e| {
+                        (unit_path.type_name(e.name().clone().into_inner()), e.visibility())
+                    })
+            })
+            .collect::<HashMap<TypeName, Visibility>>();
+
+        let deprecated = self.units.iter()
+            .flat_map(|unit| {
+                let unit_path = PackagePath::from(unit.source());
+                unit.types().iter()
+                    .filter_map(move |e| {
+                        find_attribute(e.attributes(), "deprecated")
+                            .map(|attr| {
+                                (unit_path.type_name(e.name().clone().into_inner()), attr.arg())
+                            })
+                    })
+            })
+            .collect::<HashMap<TypeName, Option<String>>>();
+
+        for unit in self.units.iter() {
+            let unit_path = PackagePath::from(unit.source());
+
+            let is_visible = |ty: &TypeName| {
+                ty.package().as_ref() == Some(&unit_path)
+                    || visibility.get(ty).is_none_or(|e| e.is_public())
+            };
+
+            // a deprecated type brought in by a `use` is reported there,
+            // rather than at each reference through it
+            let mut used_deprecated = HashSet::new();
+
+            for each in unit.uses() {
+                let type_name = each.type_name();
+                if let Some(message) = deprecated.get(&type_name) {
+                    warnings.push(BuildWarning::Deprecated(unit.source(), type_name.clone(), message.clone()));
+                    used_deprecated.insert(type_name.clone().into_inner());
+                }
+                if !is_visible(&type_name) {
+                    errors.push(BuildError::TypeNotVisible(unit.source(), type_name));
+                }
+            }
+
             for defn in unit.types() {
+                let defn_name = unit_path.type_name(defn.name().clone().into_inner());
+
                 // these should be fully-qualified now
                 let referenced = defn.referenced_types();
 
                 for each in referenced {
                     if !world.contains(&each.clone().into_inner()) {
-                        println!("{:?}", world);
-                        todo!("failed to inter-unit link for {:?}", each)
+                        // such as one defined by a since removed unit
+                        errors.push(BuildError::TypeNotFound(unit.source(), each));
+                        continue;
+                    }
+
+                    if let Some(message) = deprecated.get(&each) {
+                        if *each != defn_name && !used_deprecated.contains(&*each) {
+                            warnings.push(BuildWarning::Deprecated(unit.source(), each.clone(), message.clone()));
+                        }
+                    }
+
+                    if !is_visible(&each) {
+                        errors.push(BuildError::TypeNotVisible(unit.source(), each));
                     }
                 }
             }
         }
 
-        //println!("{:?}", world);
+        let definitions = self.units.iter()
+            .flat_map(|unit| {
+                let unit_path = PackagePath::from(unit.source());
+                unit.types().iter()
+                    .map(move |e| {
+                        (unit_path.type_name(e.name().clone().into_inner()), e.ty())
+                    })
+            })
+            .collect::<HashMap<TypeName, &Located<Type>>>();
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        drop(phase);
+
+        // record references before simplification inlines any aliases away
+        let dependencies = self.dependencies();
+        let affected = previous.map(|(previous, changed)| self.affected(previous, changed, &dependencies));
+
+        // only units defining an affected type need checking afresh
+        let is_affected = |unit: &CompilationUnit| {
+            match (previous, &affected) {
+                (Some((_, changed)), Some(affected)) => {
+                    let unit_path = PackagePath::from(unit.source());
+                    changed.contains(&unit.source())
+                        || unit.types().iter().any(|e| affected.contains(&unit_path.type_name(e.name().clone().into_inner())))
+                }
+                _ => true,
+            }
+        };
 
-        let mut runtime = Runtime::new();
+        let units = self.units.iter()
+            .filter(|unit| is_affected(unit))
+            .collect::<Vec<_>>();
 
-        for unit in &self.units {
+        if let Some((previous, _)) = previous {
+            // the checks of the rest still hold, bar deprecations, which
+            // have been reported again above
+            warnings.extend(
+                previous.warnings.iter()
+                    .filter(|e| !matches!(e, BuildWarning::Deprecated(..)))
+                    .filter(|e| self.units.iter().any(|unit| unit.source() == *e.source() && !is_affected(unit)))
+                    .cloned()
+            );
+        }
+
+        let phase = tracing::debug_span!("check", units = units.len()).entered();
+        let mut linter = Linter::new(&definitions);
+        linter.lint(&units);
+        for warning in linter.into_warnings() {
+            match warning {
+                BuildWarning::Lint(lint, _, _) if self.lints.level(lint) == LintLevel::Allow => {}
+                BuildWarning::Lint(lint, _, _) if self.lints.level(lint) == LintLevel::Deny => {
+                    errors.push(BuildError::Denied(warning));
+                }
+                _ => warnings.push(warning),
+            }
+        }
+
+        for unit in &units {
             let unit_path = PackagePath::from(unit.source());
+            let mut cycles = CycleChecker::new(unit.source(), &definitions);
+            for defn in unit.types() {
+                cycles.check(&unit_path.type_name(defn.name().clone().into_inner()), defn.ty());
+            }
+            errors.extend(cycles.into_errors());
+        }
 
-            unit.types().iter()
-                .map(|e| {
-                    (Located::new(
-                        unit_path.type_name(e.name().clone().into_inner()),
-                        e.location(),
-                    ), e.ty())
-                })
-                .for_each(|(path, ty)| {
-                    runtime.define(path.into_inner(), ty);
-                })
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        for unit in &units {
+            let mut checker = TypeChecker::new(unit.source(), &definitions);
+            for defn in unit.types() {
+                checker.check(defn.ty(), &ExprType::Unknown);
+            }
+            errors.extend(checker.into_errors());
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        drop(phase);
+
+        let phase = tracing::debug_span!("simplify").entered();
+        let mut simplified = HashMap::new();
+        for unit in &units {
+            let unit_path = PackagePath::from(unit.source());
+            let mut simplifier = Simplifier::new(unit.source(), &definitions);
+            for defn in unit.types() {
+                simplified.insert(
+                    unit_path.type_name(defn.name().clone().into_inner()),
+                    simplifier.simplify(defn.ty()),
+                );
+            }
+            warnings.extend(simplifier.into_warnings());
+        }
+
+        if self.lints.deny_warnings() && !warnings.is_empty() {
+            return Err(warnings.into_iter().map(BuildError::Denied).collect());
+        }
+
+        drop(phase);
+
+        let _phase = tracing::debug_span!("define").entered();
+
+        let mut runtime = Runtime::new(warnings, dependencies, self.limits.clone());
+
+        for unit in self.units.iter() {
+            let unit_path = PackagePath::from(unit.source());
+
+            for defn in unit.types() {
+                let path = unit_path.type_name(defn.name().clone().into_inner());
+                if let (Some((previous, _)), Some(affected)) = (previous, &affected) {
+                    if !affected.contains(&path) && runtime.share(&path, previous) {
+                        continue;
+                    }
+                }
+                if let Some(ty) = simplified.get(&path) {
+                    runtime.define(path, defn, ty);
+                }
+            }
         }
 
-        for (path, package) in &self.packages {
+        for (path, package) in self.packages.iter() {
             for (fn_name, func) in package.functions() {
                 let path = path.type_name(fn_name);
+                let func = match self.cache {
+                    Some(cache) => cache.wrap(&path, func),
+                    None => func,
+                };
                 runtime.define_function(path, func);
             }
         }
 
-        Ok(runtime)
+        runtime.find_async_types();
+
+        tracing::debug!(types = runtime.types.len(), warnings = runtime.warnings.len(), "linked");
+        Ok(Arc::new(runtime))
+    }
+
+    /// Types defined by a changed unit, added or removed since `previous`,
+    /// or depending on any of those.
+    fn affected(&self, previous: &Runtime, changed: &HashSet<Source>, dependencies: &DependencyGraph) -> HashSet<TypeName> {
+        let current = self.units.iter()
+            .flat_map(|unit| {
+                let unit_path = PackagePath::from(unit.source());
+                unit.types().iter()
+                    .map(move |e| (unit.source(), unit_path.type_name(e.name().clone().into_inner())))
+            })
+            .collect::<Vec<_>>();
+
+        let previous = previous.types.keys().cloned().collect::<HashSet<_>>();
+
+        let mut seeds = current.iter()
+            .filter(|(source, name)| changed.contains(source) || !previous.contains(name))
+            .map(|(_, name)| name.clone())
+            .collect::<HashSet<_>>();
+
+        // removed types, whose dependents now reference nothing
+        seeds.extend(
+            previous.into_iter()
+                .filter(|name| !current.iter().any(|(_, e)| e == name))
+        );
+
+        let mut affected = seeds.clone();
+        for seed in &seeds {
+            affected.extend(dependencies.affected_by(seed));
+        }
+        affected
+    }
+
+    fn dependencies(&self) -> DependencyGraph {
+        let mut graph = DependencyGraph::default();
+
+        graph.add_node(TypeName::new("int".into()), NodeKind::Primordial, Visibility::Public);
+
+        for (path, package) in self.packages.iter() {
+            for fn_name in package.function_names() {
+                graph.add_node(path.type_name(fn_name), NodeKind::Function, Visibility::Public);
+            }
+        }
+
+        for unit in self.units.iter() {
+            let unit_path = PackagePath::from(unit.source());
+            for defn in unit.types() {
+                let defn_name = unit_path.type_name(defn.name().clone().into_inner());
+                graph.add_node(defn_name.clone(), NodeKind::Type, defn.visibility());
+                for each in defn.referenced_types().iter().chain(defn.referenced_functions().iter()) {
+                    graph.add_edge(&defn_name, each);
+                }
+            }
+        }
+
+        graph
     }
 }
\ No newline at end of file
@@ -0,0 +1,206 @@
+//! Link-time simplification of type definitions before they are converted
+//! into runtime types.
+
+use std::collections::{HashMap, HashSet};
+use crate::lang::ty::{Field, ObjectType, Type, TypeName};
+use crate::lang::{Located, Source};
+use crate::runtime::BuildWarning;
+
+pub(crate) struct Simplifier<'t> {
+    source: Source,
+    types: &'t HashMap<TypeName, &'t Located<Type>>,
+    warnings: Vec<BuildWarning>,
+}
+
+impl<'t> Simplifier<'t> {
+    pub(crate) fn new(source: Source, types: &'t HashMap<TypeName, &'t Located<Type>>) -> Self {
+        Self {
+            source,
+            types,
+            warnings: Vec::new(),
+        }
+    }
+
+    pub(crate) fn into_warnings(self) -> Vec<BuildWarning> {
+        self.warnings
+    }
+
+    pub(crate) fn simplify(&mut self, ty: &Located<Type>) -> Located<Type> {
+        let location = ty.location();
+        let simplified = match &**ty {
+            Type::Ref(name) => {
+                if let Some(inlined) = self.inline(name, &mut HashSet::new()) {
+                    inlined
+                } else {
+                    Type::Ref(name.clone())
+                }
+            }
+            Type::Expr(expr) => {
+                let expr = match expr.simplify_expr() {
+                    Ok(simplified) => simplified,
+                    Err(err) => {
+                        self.warnings.push(BuildWarning::Unfoldable(self.source.clone(), expr.location(), err));
+                        expr.clone()
+                    }
+                };
+                match expr.try_get_constant().and_then(|v| v.try_get_boolean()) {
+                    Some(true) => Type::Anything,
+                    Some(false) => {
+                        self.warnings.push(BuildWarning::Unsatisfiable(self.source.clone(), location.clone()));
+                        Type::Nothing
+                    }
+                    None => Type::Expr(expr),
+                }
+            }
+            Type::Meet(lhs, rhs) => {
+                let lhs = self.simplify(lhs);
+                let rhs = self.simplify(rhs);
+                match (&*lhs, &*rhs) {
+                    (Type::Nothing, _) | (_, Type::Nothing) => Type::Nothing,
+                    (Type::Anything, _) => rhs.into_inner(),
+                    (_, Type::Anything) => lhs.into_inner(),
+                    (Type::Const(l), Type::Const(r)) => {
+                        if l == r {
+                            lhs.into_inner()
+                        } else {
+                            self.warnings.push(BuildWarning::Unsatisfiable(self.source.clone(), location.clone()));
+                            Type::Nothing
+                        }
+                    }
+                    _ => Type::Meet(Box::new(lhs), Box::new(rhs)),
+                }
+            }
+            Type::Join(lhs, rhs) => {
+                let lhs = self.simplify(lhs);
+                let rhs = self.simplify(rhs);
+                match (&*lhs, &*rhs) {
+                    (Type::Anything, _) | (_, Type::Anything) => Type::Anything,
+                    (Type::Nothing, _) => rhs.into_inner(),
+                    (_, Type::Nothing) => lhs.into_inner(),
+                    (Type::Const(l), Type::Const(r)) if l == r => lhs.into_inner(),
+                    _ => Type::Join(Box::new(lhs), Box::new(rhs)),
+                }
+            }
+            Type::Object(obj) => {
+                let mut simplified = ObjectType::new();
+                for field in obj.fields() {
+                    simplified.add_field(Located::new(
+                        Field::new(
+                            field.comments().clone(),
+                            field.attributes().clone(),
+                            field.name().clone(),
+                            self.simplify(field.ty()),
+                        ),
+                        field.location(),
+                    ));
+                }
+                Type::Object(simplified)
+            }
+            Type::Functional(name, inner) => {
+                Type::Functional(name.clone(), inner.as_ref().map(|e| Box::new(self.simplify(e))))
+            }
+            Type::List(inner) => Type::List(Box::new(self.simplify(inner))),
+            Type::Message(inner, message) => Type::Message(Box::new(self.simplify(inner)), message.clone()),
+            Type::Anything | Type::Const(_) | Type::Nothing => return ty.clone(),
+        };
+
+        Located::new(simplified, location)
+    }
+
+    /// Resolve a reference to a definition that is itself only an alias,
+    /// a constant, `anything` or `nothing`.
+    fn inline(&self, name: &Located<TypeName>, visited: &mut HashSet<TypeName>) -> Option<Type> {
+        if !visited.insert(name.clone().into_inner()) {
+            return None;
+        }
+
+        let target = self.types.get(&**name)?;
+        match &***target {
+            Type::Ref(inner) => {
+                Some(self.inline(inner, visited).unwrap_or_else(|| Type::Ref(inner.clone())))
+            }
+            Type::Const(_) | Type::Anything | Type::Nothing => Some((***target).clone()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::lang::expr::{Expr, ExprError, ValueError};
+    use crate::lang::ty::{PackagePath, TypeName};
+    use crate::runtime::sources::Ephemeral;
+    use crate::runtime::linker::lint::Lint;
+    use crate::runtime::{BuildWarning, Builder, Runtime, RuntimeType};
+    use serde_json::json;
+
+    fn with_type<R>(runtime: &Runtime, path: &str, f: impl FnOnce(&RuntimeType) -> R) -> R {
+        f(&runtime.types.get(&TypeName::from(path.to_string())).unwrap().ty)
+    }
+
+    #[actix_rt::test]
+    async fn simplified_types_evaluate() {
+        let src = Ephemeral::new(PackagePath::from_parts(vec!["foo", "bar"]), r#"
+            type answer = 42
+            type also-answer = answer
+            pub type bob = also-answer || $(1 + 1 == 3)
+            pub type jim = int && $(2 * 3 > 5 && self > 1) && int
+        "#.into());
+
+        let mut builder = Builder::new();
+        builder.build(src.iter()).unwrap();
+        let runtime = builder.link().unwrap();
+
+        assert!(matches!(
+            runtime.warnings().as_slice(),
+            [BuildWarning::Lint(Lint::UnreachableBranch, _, _), BuildWarning::Unsatisfiable(_, _)]
+        ));
+        assert!(with_type(&runtime, "foo::bar::bob", |ty| matches!(ty, RuntimeType::Const(_))));
+        assert!(with_type(&runtime, "foo::bar::jim", |ty| matches!(
+            ty,
+            RuntimeType::Meet(terms)
+                if terms.len() == 3
+                    && matches!(&**terms[1], RuntimeType::Expr(expr) if matches!(&***expr, Expr::GreaterThan(_, _)))
+        )));
+
+        let value = (&json!(42)).into();
+        let result = runtime.evaluate("foo::bar::bob".into(), &value).await.unwrap();
+        assert!(result.matches());
+    }
+
+    #[test]
+    fn warn_unsatisfiable_meet() {
+        let src = Ephemeral::new(PackagePath::from_parts(vec!["foo", "bar"]), r#"
+            pub type bob = 1 && 2
+        "#.into());
+
+        let mut builder = Builder::new();
+        builder.build(src.iter()).unwrap();
+        let runtime = builder.link().unwrap();
+
+        assert!(matches!(
+            runtime.warnings().as_slice(),
+            [BuildWarning::Unsatisfiable(_, location)] if location.span() == (28..34)
+        ));
+    }
+
+    #[test]
+    fn warn_unfoldable_expr() {
+        let src = Ephemeral::new(PackagePath::from_parts(vec!["foo", "bar"]), r#"
+            pub type bob = $(self > 1 / 0)
+        "#.into());
+
+        let mut builder = Builder::new();
+        builder.build(src.iter()).unwrap();
+        let runtime = builder.link().unwrap();
+
+        assert!(matches!(
+            runtime.warnings().as_slice(),
+            [BuildWarning::Unfoldable(_, location, ExprError::Value(ValueError::DivideByZero))] if location.span() == (30..42)
+        ));
+        assert!(with_type(&runtime, "foo::bar::bob", |ty| matches!(
+            ty,
+            RuntimeType::Expr(expr) if matches!(&***expr, Expr::GreaterThan(_, rhs) if matches!(&***rhs, Expr::Divide(_, _)))
+        )));
+    }
+}
@@ -0,0 +1,370 @@
+//! Static checking of `$( )` expressions against the type they appear within.
+
+use std::collections::{HashMap, HashSet};
+use crate::lang::expr::Expr;
+use crate::lang::ty::{Type, TypeName};
+use crate::lang::{Located, Source};
+use crate::runtime::BuildError;
+use crate::value::Value;
+
+/// The statically known shape of `self`, or of an expression.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExprType {
+    Integer,
+    Decimal,
+    Boolean,
+    String,
+    List,
+    /// An object, along with its declared fields when they are known.
+    /// Objects are open, so other fields may be present too.
+    Object(Option<Vec<(String, ExprType)>>),
+    Unknown,
+}
+
+impl ExprType {
+    fn of_value(value: &Value) -> Self {
+        if value.is_integer() {
+            ExprType::Integer
+        } else if value.is_decimal() {
+            ExprType::Decimal
+        } else if value.is_boolean() {
+            ExprType::Boolean
+        } else if value.is_string() {
+            ExprType::String
+        } else if value.is_list() {
+            ExprType::List
+        } else if value.is_object() {
+            ExprType::Object(None)
+        } else {
+            ExprType::Unknown
+        }
+    }
+
+    fn is_numeric(&self) -> bool {
+        matches!(self, ExprType::Integer | ExprType::Decimal | ExprType::Unknown)
+    }
+
+    fn is_boolean(&self) -> bool {
+        matches!(self, ExprType::Boolean | ExprType::Unknown)
+    }
+
+    fn is_known(&self) -> bool {
+        !matches!(self, ExprType::Unknown)
+    }
+
+    /// What a value matching both `self` and `other` is known to look like.
+    fn meet(self, other: ExprType) -> ExprType {
+        match (self, other) {
+            (ExprType::Object(Some(mut fields)), ExprType::Object(Some(other))) => {
+                for (name, ty) in other {
+                    if let Some(i) = fields.iter().position(|(field, _)| *field == name) {
+                        let (name, existing) = fields.remove(i);
+                        fields.insert(i, (name, existing.meet(ty)));
+                    } else {
+                        fields.push((name, ty));
+                    }
+                }
+                ExprType::Object(Some(fields))
+            }
+            (ExprType::Object(None), ExprType::Object(fields))
+            | (ExprType::Object(fields), ExprType::Object(None)) => ExprType::Object(fields),
+            (ExprType::Unknown, other) => other,
+            // otherwise nothing matches both, which is for the evaluation to report
+            (ty, _) => ty,
+        }
+    }
+}
+
+pub(crate) struct TypeChecker<'t> {
+    source: Source,
+    types: &'t HashMap<TypeName, &'t Located<Type>>,
+    errors: Vec<BuildError>,
+}
+
+impl<'t> TypeChecker<'t> {
+    pub(crate) fn new(source: Source, types: &'t HashMap<TypeName, &'t Located<Type>>) -> Self {
+        Self {
+            source,
+            types,
+            errors: Vec::new(),
+        }
+    }
+
+    pub(crate) fn into_errors(self) -> Vec<BuildError> {
+        self.errors
+    }
+
+    pub(crate) fn check(&mut self, ty: &Located<Type>, context: &ExprType) {
+        match &**ty {
+            Type::Expr(expr) => {
+                let result = self.infer(expr, context);
+                if !result.is_boolean() {
+                    self.errors.push(BuildError::NonBooleanPredicate(self.source.clone(), expr.location(), result));
+                }
+            }
+            Type::Meet(lhs, rhs) => {
+                // every side of a meet constrains the same value
+                let shape = self.shape(ty, &mut HashSet::new());
+                let context = if shape.is_known() {
+                    &shape
+                } else {
+                    context
+                };
+                self.check(lhs, context);
+                self.check(rhs, context);
+            }
+            Type::Join(lhs, rhs) => {
+                self.check(lhs, context);
+                self.check(rhs, context);
+            }
+            Type::Message(inner, _) => {
+                self.check(inner, context);
+            }
+            Type::Object(obj) => {
+                for field in obj.fields() {
+                    self.check(field.ty(), &ExprType::Unknown);
+                }
+            }
+            Type::List(inner) => {
+                self.check(inner, &ExprType::Unknown);
+            }
+            Type::Functional(_, Some(inner)) => {
+                self.check(inner, &ExprType::Unknown);
+            }
+            Type::Functional(_, None)
+            | Type::Anything
+            | Type::Ref(_)
+            | Type::Const(_)
+            | Type::Nothing => {}
+        }
+    }
+
+    /// What a value matching `ty` is known to look like.
+    fn shape(&self, ty: &Located<Type>, visited: &mut HashSet<TypeName>) -> ExprType {
+        match &**ty {
+            Type::Ref(name) => {
+                if !name.is_qualified() && name.name() == "int" {
+                    ExprType::Integer
+                } else if !visited.insert(name.clone().into_inner()) {
+                    ExprType::Unknown
+                } else if let Some(ty) = self.types.get(&**name) {
+                    self.shape(ty, visited)
+                } else {
+                    ExprType::Unknown
+                }
+            }
+            Type::Const(value) => ExprType::of_value(value),
+            Type::Object(obj) => ExprType::Object(Some(
+                obj.fields().iter()
+                    .map(|field| {
+                        (field.name().clone().into_inner(), self.shape(field.ty(), &mut visited.clone()))
+                    })
+                    .collect()
+            )),
+            Type::List(_) => ExprType::List,
+            Type::Meet(lhs, rhs) => {
+                let lhs = self.shape(lhs, &mut visited.clone());
+                lhs.meet(self.shape(rhs, visited))
+            }
+            Type::Join(lhs, rhs) => {
+                let lhs = self.shape(lhs, &mut visited.clone());
+                if lhs == self.shape(rhs, visited) {
+                    lhs
+                } else {
+                    ExprType::Unknown
+                }
+            }
+            Type::Message(inner, _) => self.shape(inner, visited),
+            Type::Anything
+            | Type::Expr(_)
+            | Type::Functional(_, _)
+            | Type::Nothing => ExprType::Unknown,
+        }
+    }
+
+    fn infer(&mut self, expr: &Located<Expr>, self_ty: &ExprType) -> ExprType {
+        match &**expr {
+            Expr::SelfLiteral(_) => self_ty.clone(),
+            Expr::Value(value) => ExprType::of_value(value),
+            Expr::Accessor(lhs, name) => {
+                match self.infer(lhs, self_ty) {
+                    ExprType::Object(Some(fields)) => {
+                        // a field not declared may still be present
+                        fields.iter()
+                            .find(|(field, _)| *field == **name)
+                            .map(|(_, ty)| ty.clone())
+                            .unwrap_or(ExprType::Unknown)
+                    }
+                    ExprType::Object(None) | ExprType::Unknown => ExprType::Unknown,
+                    _ => {
+                        self.errors.push(BuildError::UnknownAccessor(self.source.clone(), name.clone()));
+                        ExprType::Unknown
+                    }
+                }
+            }
+            Expr::Field(lhs, rhs) => {
+                let field_ty = self.infer(lhs, self_ty);
+                self.infer(rhs, &field_ty)
+            }
+            Expr::Function(_, operand) => {
+                self.infer(operand, self_ty);
+                ExprType::Unknown
+            }
+            Expr::Add(lhs, rhs)
+            | Expr::Subtract(lhs, rhs)
+            | Expr::Multiply(lhs, rhs)
+            | Expr::Divide(lhs, rhs) => {
+                let lhs_ty = self.infer(lhs, self_ty);
+                let rhs_ty = self.infer(rhs, self_ty);
+                match (&lhs_ty, &rhs_ty) {
+                    (ExprType::Integer, ExprType::Integer) => ExprType::Integer,
+                    (ExprType::Decimal, ExprType::Integer | ExprType::Decimal)
+                    | (ExprType::Integer, ExprType::Decimal) => ExprType::Decimal,
+                    _ => {
+                        if !lhs_ty.is_numeric() || !rhs_ty.is_numeric() {
+                            self.errors.push(BuildError::IncompatibleOperands(self.source.clone(), expr.location(), lhs_ty, rhs_ty));
+                        }
+                        ExprType::Unknown
+                    }
+                }
+            }
+            Expr::LessThan(lhs, rhs)
+            | Expr::LessThanEqual(lhs, rhs)
+            | Expr::GreaterThan(lhs, rhs)
+            | Expr::GreaterThanEqual(lhs, rhs) => {
+                let lhs_ty = self.infer(lhs, self_ty);
+                let rhs_ty = self.infer(rhs, self_ty);
+                let ordered = (lhs_ty.is_numeric() && rhs_ty.is_numeric())
+                    || !lhs_ty.is_known()
+                    || !rhs_ty.is_known()
+                    || matches!((&lhs_ty, &rhs_ty), (ExprType::String, ExprType::String) | (ExprType::Boolean, ExprType::Boolean));
+                if !ordered {
+                    self.errors.push(BuildError::IncompatibleOperands(self.source.clone(), expr.location(), lhs_ty, rhs_ty));
+                }
+                ExprType::Boolean
+            }
+            Expr::Equal(lhs, rhs)
+            | Expr::NotEqual(lhs, rhs) => {
+                let lhs_ty = self.infer(lhs, self_ty);
+                let rhs_ty = self.infer(rhs, self_ty);
+                let comparable = (lhs_ty.is_numeric() && rhs_ty.is_numeric())
+                    || !lhs_ty.is_known()
+                    || !rhs_ty.is_known()
+                    || std::mem::discriminant(&lhs_ty) == std::mem::discriminant(&rhs_ty);
+                if !comparable {
+                    self.errors.push(BuildError::IncompatibleOperands(self.source.clone(), expr.location(), lhs_ty, rhs_ty));
+                }
+                ExprType::Boolean
+            }
+            Expr::Not(operand) => {
+                self.expect_boolean(operand, self_ty);
+                ExprType::Boolean
+            }
+            Expr::LogicalAnd(lhs, rhs)
+            | Expr::LogicalOr(lhs, rhs) => {
+                self.expect_boolean(lhs, self_ty);
+                self.expect_boolean(rhs, self_ty);
+                ExprType::Boolean
+            }
+        }
+    }
+
+    fn expect_boolean(&mut self, expr: &Located<Expr>, self_ty: &ExprType) {
+        let ty = self.infer(expr, self_ty);
+        if !ty.is_boolean() {
+            self.errors.push(BuildError::NonBooleanPredicate(self.source.clone(), expr.location(), ty));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+    use crate::lang::ty::PackagePath;
+    use crate::runtime::sources::Ephemeral;
+    use crate::runtime::{BuildError, Builder};
+    use super::ExprType;
+
+    fn link(src: &str) -> Result<(), Vec<BuildError>> {
+        let src = Ephemeral::new(PackagePath::from_parts(vec!["foo", "bar"]), src.into());
+        let mut builder = Builder::new();
+        builder.build(src.iter()).unwrap();
+        builder.link().map(|_| ())
+    }
+
+    #[test]
+    fn accept_well_typed_exprs() {
+        assert!(link(r#"
+            pub type positive = int && $(self > 0)
+            pub type small = positive && $(self * 2 <= 10.5)
+            pub type anything-bigger = $(self > 2)
+            pub type named = {
+                name: $(self == "bob" || self == "jim"),
+            }
+        "#).is_ok());
+    }
+
+    #[test]
+    fn reject_impossible_comparison() {
+        let errors = link(r#"
+            pub type bob = int && $(self > "abc")
+        "#).err().unwrap();
+
+        assert!(matches!(
+            errors.as_slice(),
+            [BuildError::IncompatibleOperands(_, location, _, _)] if location.span() == (37..49)
+        ));
+    }
+
+    #[test]
+    fn check_accessors() {
+        assert!(link(r#"
+            pub type adult = { age: int } && $(self.age >= 18 && self.nickname != "kid")
+            pub type named = { name: "bob" } && { age: int } && $(self.name == "bob" && self.age > 0)
+        "#).is_ok());
+
+        let errors = link(r#"
+            pub type bob = { name: "bob" } && { age: int } && $(self.age == "old")
+            pub type jim = int && $(self.age > 0)
+        "#).err().unwrap();
+
+        assert!(matches!(
+            errors.as_slice(),
+            [
+                BuildError::IncompatibleOperands(_, _, ExprType::Integer, ExprType::String),
+                BuildError::UnknownAccessor(_, name),
+            ] if &**name == "age"
+        ));
+    }
+
+    #[test]
+    fn evaluate_accessors() {
+        let src = Ephemeral::new(PackagePath::from_parts(vec!["foo", "bar"]), r#"
+            pub type adult = { age: int } && $(self.age >= 18)
+        "#.into());
+        let mut builder = Builder::new();
+        builder.build(src.iter()).unwrap();
+        let runtime = builder.link().unwrap();
+
+        let adult = (&json!({ "age": 42, "name": "bob" })).into();
+        assert!(runtime.evaluate_blocking("foo::bar::adult".into(), &adult).unwrap().matches());
+        let child = (&json!({ "age": 4 })).into();
+        assert!(!runtime.evaluate_blocking("foo::bar::adult".into(), &child).unwrap().matches());
+    }
+
+    #[test]
+    fn reject_non_boolean_predicate() {
+        let errors = link(r#"
+            pub type bob = $(self + true)
+            pub type jim = int && $(self + 1)
+        "#).err().unwrap();
+
+        assert!(matches!(
+            errors.as_slice(),
+            [
+                BuildError::IncompatibleOperands(_, _, _, _),
+                BuildError::NonBooleanPredicate(_, _, _),
+            ]
+        ));
+    }
+}
@@ -1,29 +1,77 @@
 pub mod sources;
 pub mod linker;
+pub mod graph;
+pub mod handle;
+pub mod coverage;
+pub mod limits;
+pub mod observer;
+pub mod profile;
+pub mod program;
 
 use std::borrow::BorrowMut;
 use std::cell::{Cell, RefCell};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::{Debug, Formatter};
 use std::future::{Future, ready};
 use std::mem;
-use std::pin::Pin;
-use std::sync::{Arc, Mutex};
-use std::task::ready;
-use chumsky::{Error, Stream};
+use std::pin::{pin, Pin};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::task::{Context, Poll, ready, Waker};
+use chumsky::Stream;
+use futures_util::{stream, StreamExt};
+use tracing::Instrument;
 use crate::function::{Function, FunctionPackage};
-use crate::lang::{CompilationUnit, Located, ParserError, ParserInput, PolicyParser, Source};
-use crate::lang::expr::Expr;
-use crate::lang::ty::{PackagePath, Type, TypeName};
-use crate::value::{Value as RuntimeValue, Value};
+use crate::function::memo::FunctionCache;
+use crate::lang::{CompilationUnit, Located, Location, ParserError, PolicyParser, Source, Span};
+use crate::lang::expr::{Expr, ExprError};
+use crate::lang::attr::Attribute;
+use crate::lang::ty::{PackagePath, Type, TypeDefn, TypeName, Visibility};
+use crate::value::{Annotation, Noted, Value as RuntimeValue, Value};
+use crate::runtime::graph::DependencyGraph;
+use crate::runtime::limits::{Budget, Limits};
+use crate::runtime::observer::Observer;
 use crate::runtime::linker::Linker;
+use crate::runtime::linker::lint::{Lint, LintConfig, LintLevel};
+use crate::runtime::linker::typecheck::ExprType;
 
 #[derive(Debug)]
 pub enum BuildError {
-    TypeNotFound,
+    TypeNotFound(Source, Located<TypeName>),
+    TypeNotVisible(Source, Located<TypeName>),
+    IncompatibleOperands(Source, Location, ExprType, ExprType),
+    NonBooleanPredicate(Source, Location, ExprType),
+    UnknownAccessor(Source, Located<String>),
+    /// A reference that leads back to its own definition without descending
+    /// into an object field or list element.
+    NonProductiveCycle(Source, Located<TypeName>),
+    /// A warning promoted to an error by the lint configuration.
+    Denied(BuildWarning),
     Parser(ParserError),
 }
 
+#[derive(Clone, Debug)]
+pub enum BuildWarning {
+    Deprecated(Source, Located<TypeName>, Option<String>),
+    /// A type that no value can ever match.
+    Unsatisfiable(Source, Location),
+    /// A constant expression which failed to fold, such as a division by
+    /// zero, left as is to fail when evaluated.
+    Unfoldable(Source, Location, ExprError),
+    Lint(Lint, Source, Location),
+}
+
+impl BuildWarning {
+    pub fn source(&self) -> &Source {
+        match self {
+            BuildWarning::Deprecated(source, _, _) => source,
+            BuildWarning::Unsatisfiable(source, _) => source,
+            BuildWarning::Unfoldable(source, _, _) => source,
+            BuildWarning::Lint(_, source, _) => source,
+        }
+    }
+}
+
 impl From<ParserError> for BuildError {
     fn from(inner: ParserError) -> Self {
         Self::Parser(inner)
@@ -33,6 +81,11 @@ impl From<ParserError> for BuildError {
 pub struct Builder {
     units: Vec<CompilationUnit>,
     packages: HashMap<PackagePath, FunctionPackage>,
+    lints: LintConfig,
+    limits: Limits,
+    cache: Option<Arc<FunctionCache>>,
+    changed: HashSet<Source>,
+    runtime: Option<Arc<Runtime>>,
 }
 
 impl Builder {
@@ -40,14 +93,19 @@ impl Builder {
         Self {
             units: Default::default(),
             packages: Default::default(),
+            lints: Default::default(),
+            limits: Default::default(),
+            cache: None,
+            changed: Default::default(),
+            runtime: None,
         }
     }
 
     pub fn build<'a, Iter, S, SrcIter>(&mut self, sources: SrcIter) -> Result<(), Vec<BuildError>>
         where
             Self: Sized,
-            Iter: Iterator<Item=(ParserInput, <ParserError as Error<ParserInput>>::Span)> + 'a,
-            S: Into<Stream<'a, ParserInput, <ParserError as Error<ParserInput>>::Span, Iter>>,
+            Iter: Iterator<Item=(char, Span)> + 'a,
+            S: Into<Stream<'a, char, Span, Iter>>,
             SrcIter: Iterator<Item=(Source, S)>,
     {
         let mut errors = Vec::new();
@@ -74,28 +132,75 @@ impl Builder {
         }
     }
 
+    /// Replaces any unit previously built from the same source.
     fn add_compilation_unit(&mut self, unit: CompilationUnit) {
+        self.changed.insert(unit.source());
+        self.units.retain(|e| e.source() != unit.source());
         self.units.push(unit)
     }
 
+    pub fn remove(&mut self, source: &Source) {
+        self.changed.insert(source.clone());
+        self.units.retain(|e| e.source() != *source);
+    }
+
     pub fn add_function_package(&mut self, path: PackagePath, package: FunctionPackage) {
         self.packages.insert(path, package);
     }
 
-    pub fn link(self) -> Result<Arc<Runtime>, Vec<BuildError>> {
-        Linker::new(self.units, self.packages).link()
+    pub fn set_lint_level(&mut self, lint: Lint, level: LintLevel) {
+        self.lints.set_level(lint, level);
+    }
+
+    /// Fail linking on any warning, such as in CI.
+    pub fn set_deny_warnings(&mut self, deny_warnings: bool) {
+        self.lints.set_deny_warnings(deny_warnings);
+    }
+
+    /// Limits applied to each evaluation against the linked runtime.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Memoize calls to the functions opted into `cache`. The cache is
+    /// kept by each runtime linked, so may be shared with relinked ones.
+    pub fn set_function_cache(&mut self, cache: Arc<FunctionCache>) {
+        self.cache.replace(cache);
+    }
+
+    pub fn link(mut self) -> Result<Arc<Runtime>, Vec<BuildError>> {
+        Linker::new(&mut self.units, &self.packages, &self.lints, &self.limits, self.cache.as_ref()).link()
+    }
+
+    /// Link only what changed since the previous `relink`, sharing the
+    /// unaffected types of the runtime it produced.
+    pub fn relink(&mut self) -> Result<Arc<Runtime>, Vec<BuildError>> {
+        let linker = Linker::new(&mut self.units, &self.packages, &self.lints, &self.limits, self.cache.as_ref());
+        let runtime = if let Some(previous) = &self.runtime {
+            linker.relink(previous, &self.changed)?
+        } else {
+            linker.link()?
+        };
+
+        self.changed.clear();
+        self.runtime = Some(runtime.clone());
+        Ok(runtime)
     }
 }
 
 #[derive(Debug)]
 pub struct EvaluationResult {
     value: Option<Value>,
+    messages: Vec<String>,
+    annotations: Vec<Annotation>,
 }
 
 impl EvaluationResult {
     pub fn new() -> Self {
         Self {
             value: None,
+            messages: Default::default(),
+            annotations: Default::default(),
         }
     }
 
@@ -104,6 +209,53 @@ impl EvaluationResult {
         self
     }
 
+    pub(crate) fn add_messages(mut self, messages: &[String]) -> Self {
+        self.messages.extend_from_slice(messages);
+        self
+    }
+
+    pub(crate) fn note<N: Into<Noted>>(mut self, noted: N, matches: bool) -> Self {
+        if matches {
+            self.annotations.push(Annotation::Match(String::new(), noted.into()));
+        } else {
+            self.annotations.push(Annotation::Nonmatch(String::new(), noted.into()));
+        }
+        self
+    }
+
+    /// Note whether `ty` matched `value`, telling any observer.
+    fn note_type(self, ty: &Arc<Located<RuntimeType>>, value: &Value, matches: bool, observer: Option<&dyn Observer>) -> Self {
+        if let Some(observer) = observer {
+            observer.note(&ty.clone().into(), value, matches);
+        }
+        self.note(ty.clone(), matches)
+    }
+
+    pub(crate) fn add_annotations(mut self, annotations: impl IntoIterator<Item=Annotation>) -> Self {
+        self.annotations.extend(annotations);
+        self
+    }
+
+    /// This result's annotations, re-based onto `segment` of an enclosing value.
+    fn annotations_within<'r>(&'r self, segment: &'r str) -> impl Iterator<Item=Annotation> + 'r {
+        self.annotations.iter().map(move |e| {
+            let mut e = e.clone();
+            e.rebase(segment);
+            e
+        })
+    }
+
+    /// What was learned about the value and its parts while evaluating,
+    /// leaving the evaluated value itself untouched.
+    pub fn annotations(&self) -> &Vec<Annotation> {
+        &self.annotations
+    }
+
+    /// Policy-authored messages explaining why the value did not match.
+    pub fn messages(&self) -> &Vec<String> {
+        &self.messages
+    }
+
     pub fn value(&self) -> &Option<Value> {
         &self.value
     }
@@ -117,46 +269,308 @@ impl EvaluationResult {
     }
 }
 
+/// How much of a type to evaluate once its outcome is known.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum EvaluationMode {
+    /// Stop at the first failing field, element or term of an object,
+    /// list or meet, and at the first matching term of a join, as for
+    /// admission decisions.
+    #[default]
+    Fast,
+    /// Evaluate every field, element and term, reporting every violation,
+    /// as for audit reports.
+    Exhaustive,
+}
+
 #[derive(Debug)]
 pub enum RuntimeError {
     NoSuchType(String),
+    /// Blocking evaluation reached this async function.
+    RequiresExecutor(String),
+    /// Evaluation descended deeper into a value than the limit allows.
+    DepthExceeded(usize),
+    /// Evaluation reached more types than the limit allows.
+    FuelExhausted(u64),
+    /// Evaluation took longer than the limit allows.
+    DeadlineExceeded(Duration),
+    /// This function took longer than the limit allows.
+    FunctionTimeout(String, Duration),
+}
+
+pub(crate) fn private_type_hint(path: &TypeName) {
+    tracing::warn!(name = %path.as_type_str(), "type is private to its package; declare it `pub type` to evaluate it");
+}
+
+/// Drives an evaluation to completion for [`Runtime::evaluate_with`].
+pub trait Executor {
+    fn block_on<'f>(&self, evaluation: Pin<Box<dyn Future<Output=Result<EvaluationResult, RuntimeError>> + Send + 'f>>) -> Result<EvaluationResult, RuntimeError>;
 }
 
+#[derive(Clone)]
+struct RuntimeTypeDefn {
+    attributes: Vec<Located<Attribute>>,
+    visibility: Visibility,
+    ty: Arc<Located<RuntimeType>>,
+}
+
+impl RuntimeTypeDefn {
+    fn new(attributes: Vec<Located<Attribute>>, visibility: Visibility, ty: Located<RuntimeType>) -> Self {
+        Self {
+            attributes,
+            visibility,
+            ty: Arc::new(ty),
+        }
+    }
+}
+
+/// An immutable snapshot of linked types. Relinking produces a new
+/// snapshot rather than altering this one; see [`handle::RuntimeHandle`].
 pub struct Runtime {
-    types: Mutex<HashMap<TypeName, Arc<Located<RuntimeType>>>>,
+    types: HashMap<TypeName, RuntimeTypeDefn>,
+    warnings: Vec<BuildWarning>,
+    dependencies: DependencyGraph,
+    limits: Limits,
+    // types reaching any async function
+    async_types: HashSet<TypeName>,
 }
 
 impl Runtime {
-    pub(crate) fn new() -> Arc<Self> {
-        let this = Arc::new(Self {
-            types: Mutex::new(Default::default())
-        });
-
-        this.types.lock().unwrap().insert(
+    pub(crate) fn new(warnings: Vec<BuildWarning>, dependencies: DependencyGraph, limits: Limits) -> Self {
+        let mut this = Self {
+            types: Default::default(),
+            warnings,
+            dependencies,
+            limits,
+            async_types: Default::default(),
+        };
+
+        this.types.insert(
             TypeName::new("int".into()),
-            Arc::new(Located::new(RuntimeType::Primordial(PrimordialType::Integer), 0..0)));
+            RuntimeTypeDefn::new(
+                Default::default(),
+                Visibility::Public,
+                Located::new(RuntimeType::Primordial(PrimordialType::Integer), 0..0),
+            ));
 
         this
     }
 
-    pub async fn evaluate(&self, path: String, value: &mut RuntimeValue) -> Result<EvaluationResult, RuntimeError> {
+    /// Warnings reported while linking this runtime.
+    pub fn warnings(&self) -> &Vec<BuildWarning> {
+        &self.warnings
+    }
+
+    /// How the linked types and functions reference each other.
+    pub fn dependencies(&self) -> &DependencyGraph {
+        &self.dependencies
+    }
+
+    /// The attributes attached to a public type.
+    pub fn attributes(&self, path: String) -> Result<Vec<Located<Attribute>>, RuntimeError> {
+        let path = TypeName::from(path);
+        self.types
+            .get(&path)
+            .filter(|e| e.visibility.is_public())
+            .map(|e| e.attributes.clone())
+            .ok_or_else(|| self.no_such_type(&path))
+    }
+
+    /// The fields of a public object type, along with their attributes.
+    /// Types other than objects have none.
+    pub fn fields(&self, path: String) -> Result<Vec<Arc<Located<RuntimeField>>>, RuntimeError> {
+        let path = TypeName::from(path);
+        let mut ty = self.types
+            .get(&path)
+            .filter(|e| e.visibility.is_public())
+            .map(|e| e.ty.clone())
+            .ok_or_else(|| self.no_such_type(&path))?;
+
+        // see through aliases left by simplification, and messages
+        loop {
+            ty = match &**ty {
+                RuntimeType::Object(object) => return Ok(object.fields.clone()),
+                RuntimeType::Ref(name) => match self.types.get(&**name) {
+                    Some(defn) => defn.ty.clone(),
+                    None => return Ok(Vec::new()),
+                },
+                RuntimeType::Message(inner, _) => inner.clone(),
+                _ => return Ok(Vec::new()),
+            }
+        }
+    }
+
+    /// Report a type as not found, warning when it exists but is private,
+    /// such as in policies written before types had to be declared `pub`.
+    fn no_such_type(&self, path: &TypeName) -> RuntimeError {
+        if self.types.get(path).is_some_and(|e| !e.visibility.is_public()) {
+            private_type_hint(path);
+        }
+        RuntimeError::NoSuchType(path.as_type_str())
+    }
+
+    /// Evaluate a value against a public type.
+    ///
+    /// Private types are reported as `NoSuchType`, as they are only
+    /// reachable through references from within their own package. A
+    /// warning is logged naming the type, as a hint to declare it `pub`.
+    pub async fn evaluate(&self, path: String, value: &RuntimeValue) -> Result<EvaluationResult, RuntimeError> {
+        self.evaluate_in(path, value, EvaluationMode::default()).await
+    }
+
+    /// Evaluate a value against a public type, in the given mode.
+    pub async fn evaluate_in(&self, path: String, value: &RuntimeValue, mode: EvaluationMode) -> Result<EvaluationResult, RuntimeError> {
+        self.evaluate_observing(path, value, mode, None).await
+    }
+
+    /// Evaluate a value against a public type, in the given mode, telling
+    /// `observer` about each step.
+    pub async fn evaluate_observed(&self, path: String, value: &RuntimeValue, mode: EvaluationMode, observer: &dyn Observer) -> Result<EvaluationResult, RuntimeError> {
+        self.evaluate_observing(path, value, mode, Some(observer)).await
+    }
+
+    async fn evaluate_observing(&self, path: String, value: &RuntimeValue, mode: EvaluationMode, observer: Option<&dyn Observer>) -> Result<EvaluationResult, RuntimeError> {
         let path = TypeName::from(path);
-        let ty = self.types.lock().unwrap()[&path].clone();
-        ty.evaluate(value).await
+        let ty = self.types
+            .get(&path)
+            .filter(|e| e.visibility.is_public())
+            .map(|e| e.ty.clone());
+
+        if let Some(ty) = ty {
+            let span = tracing::debug_span!("evaluation", name = %path.as_type_str(), ?mode, outcome = tracing::field::Empty);
+            let budget = Budget::new(&self.limits);
+            if let Some(observer) = observer {
+                observer.enter_type(&path);
+            }
+            let result = ty.evaluate(value, self, mode, &budget, observer, 0).instrument(span.clone()).await;
+            if let Some(observer) = observer {
+                observer.leave_type(&path, &result);
+            }
+            span.record("outcome", outcome(&result));
+            result
+        } else {
+            Err(self.no_such_type(&path))
+        }
+    }
+
+    /// Evaluate a value against a public type without an executor, which
+    /// is only possible if the type reaches no async [`Function`].
+    pub fn evaluate_blocking(&self, path: String, value: &RuntimeValue) -> Result<EvaluationResult, RuntimeError> {
+        self.evaluate_blocking_in(path, value, EvaluationMode::default())
+    }
+
+    pub fn evaluate_blocking_in(&self, path: String, value: &RuntimeValue, mode: EvaluationMode) -> Result<EvaluationResult, RuntimeError> {
+        let name = TypeName::from(path.clone());
+        if let Some(func) = self.async_functions(&name).first() {
+            return Err(RuntimeError::RequiresExecutor(func.as_type_str()));
+        }
+
+        let mut evaluation = pin!(self.evaluate_in(path, value, mode));
+        match evaluation.as_mut().poll(&mut Context::from_waker(Waker::noop())) {
+            Poll::Ready(result) => result,
+            Poll::Pending => Err(RuntimeError::RequiresExecutor(name.as_type_str())),
+        }
+    }
+
+    /// Evaluate a value against a public type, blocking on `executor`.
+    pub fn evaluate_with(&self, path: String, value: &RuntimeValue, executor: &impl Executor) -> Result<EvaluationResult, RuntimeError> {
+        executor.block_on(Box::pin(self.evaluate(path, value)))
+    }
+
+    /// Lower into a [`program::Program`], which evaluates the same types
+    /// with references resolved ahead of time.
+    pub fn compile(&self) -> program::Program {
+        program::Program::compile(self)
+    }
+
+    fn async_functions(&self, path: &TypeName) -> Vec<TypeName> {
+        self.dependencies.reachable_from(path).into_iter()
+            .filter(|node| {
+                matches!(
+                    self.types.get(node.name()).map(|e| &**e.ty),
+                    Some(RuntimeType::Primordial(PrimordialType::Function(_, func))) if func.is_async()
+                )
+            })
+            .map(|node| node.name().clone())
+            .collect()
+    }
+
+    /// Note which types reach async functions, once all are defined.
+    pub(crate) fn find_async_types(&mut self) {
+        let async_types = self.types.iter()
+            .filter(|(name, defn)| self.reaches_async(&defn.ty) || !self.async_functions(name).is_empty())
+            .map(|(name, _)| name.clone())
+            .collect();
+        self.async_types = async_types;
+    }
+
+    /// Whether evaluating `ty` may call an async function.
+    fn reaches_async(&self, ty: &RuntimeType) -> bool {
+        match ty {
+            RuntimeType::Primordial(PrimordialType::Function(_, func)) => func.is_async(),
+            RuntimeType::Ref(name) => self.async_types.contains(name),
+            RuntimeType::Functional(name, inner) => {
+                self.async_types.contains(name) || inner.as_ref().is_some_and(|e| self.reaches_async(e))
+            }
+            RuntimeType::Object(inner) => inner.fields.iter().any(|e| self.reaches_async(&e.ty)),
+            RuntimeType::Join(terms) | RuntimeType::Meet(terms) => terms.iter().any(|e| self.reaches_async(e)),
+            RuntimeType::List(inner) | RuntimeType::Message(inner, _) => self.reaches_async(inner),
+            _ => false,
+        }
+    }
+
+    /// How many of the independent `types` to evaluate at once. Only those
+    /// waiting on async functions gain from running concurrently.
+    fn concurrency<'t>(&self, mut types: impl Iterator<Item=&'t Arc<Located<RuntimeType>>>, budget: &Budget, observer: Option<&dyn Observer>) -> usize {
+        if observer.is_some_and(|e| e.sequential()) || !types.any(|e| self.reaches_async(e)) {
+            1
+        } else {
+            budget.concurrency()
+        }
     }
 
-    fn define(self: &mut Arc<Self>, path: TypeName, ty: &Located<Type>) {
-        println!("define {:?}", path.as_type_str());
+    pub(crate) async fn evaluate_internal(&self, path: &TypeName, value: &RuntimeValue, mode: EvaluationMode, budget: &Budget, observer: Option<&dyn Observer>, depth: usize) -> Result<EvaluationResult, RuntimeError> {
+        let ty = self.types
+            .get(path)
+            .map(|e| e.ty.clone());
+
+        if let Some(ty) = ty {
+            let span = tracing::trace_span!("type", name = %path.as_type_str());
+            if let Some(observer) = observer {
+                observer.enter_type(path);
+            }
+            let result = ty.evaluate(value, self, mode, budget, observer, depth).instrument(span).await;
+            if let Some(observer) = observer {
+                observer.leave_type(path, &result);
+            }
+            result
+        } else {
+            Err(RuntimeError::NoSuchType(path.as_type_str()))
+        }
+    }
+
+    fn define(&mut self, path: TypeName, defn: &TypeDefn, ty: &Located<Type>) {
+        tracing::trace!(name = %path.as_type_str(), "define");
         let converted = self.convert(ty);
 
-        self.types.lock().unwrap().insert(
+        self.types.insert(
             path,
-            Arc::new(converted),
+            RuntimeTypeDefn::new(defn.attributes().clone(), defn.visibility(), converted),
         );
     }
 
-    fn define_function(self: &mut Arc<Self>, path: TypeName, func: Arc<dyn Function>) {
-        println!("define-func {:?}", path.as_type_str());
+    /// Share the definition of `path` from a previously linked runtime,
+    /// if it has one.
+    fn share(&mut self, path: &TypeName, previous: &Runtime) -> bool {
+        if let Some(defn) = previous.types.get(path) {
+            self.types.insert(path.clone(), defn.clone());
+            true
+        } else {
+            false
+        }
+    }
+
+    fn define_function(&mut self, path: TypeName, func: Arc<dyn Function>) {
+        tracing::trace!(name = %path.as_type_str(), "define function");
 
         let runtime_type = Located::new(RuntimeType::Primordial(
             PrimordialType::Function(
@@ -165,20 +579,20 @@ impl Runtime {
             )
         ), 0..0);
 
-        self.types.lock().unwrap().insert(
+        self.types.insert(
             path,
-            Arc::new(runtime_type),
+            RuntimeTypeDefn::new(Default::default(), Visibility::Public, runtime_type),
         );
     }
 
-    fn convert(self: &Arc<Self>, ty: &Located<Type>) -> Located<RuntimeType> {
+    fn convert(&self, ty: &Located<Type>) -> Located<RuntimeType> {
         match &**ty {
             Type::Anything => {
                 Located::new(RuntimeType::Anything, ty.location())
             }
             Type::Ref(inner) => {
                 Located::new(
-                    RuntimeType::Ref(self.clone(), inner.clone()),
+                    RuntimeType::Ref(inner.clone()),
                     ty.location(),
                 )
             }
@@ -195,6 +609,7 @@ impl Runtime {
                             fields: inner.fields().iter().map(|f| {
                                 Arc::new(Located::new(
                                     RuntimeField {
+                                        attributes: f.attributes().clone(),
                                         name: f.name().clone(),
                                         ty: Arc::new(self.convert(f.ty())),
                                     },
@@ -212,29 +627,31 @@ impl Runtime {
                     ty.location(),
                 )
             }
-            Type::Join(lhs, rhs) => {
+            Type::Join(_, _) => {
                 Located::new(
                     RuntimeType::Join(
-                        Arc::new(self.convert(&**lhs)),
-                        Arc::new(self.convert(&**rhs)),
+                        self.convert_terms(ty, &|e| match e {
+                            Type::Join(lhs, rhs) => Some((lhs, rhs)),
+                            _ => None,
+                        })
                     ),
                     ty.location(),
                 )
             }
-            Type::Meet(lhs, rhs) => {
+            Type::Meet(_, _) => {
                 Located::new(
                     RuntimeType::Meet(
-                        Arc::new(self.convert(&**lhs)),
-                        Arc::new(self.convert(&**rhs)),
+                        self.convert_terms(ty, &|e| match e {
+                            Type::Meet(lhs, rhs) => Some((lhs, rhs)),
+                            _ => None,
+                        })
                     ),
                     ty.location(),
                 )
             }
             Type::Functional(fn_name, inner) => {
-                println!("lang {:?} {:?}", fn_name, inner);
                 Located::new(
                     RuntimeType::Functional(
-                        self.clone(),
                         fn_name.clone(),
                         inner.as_ref().map(|e| Arc::new(self.convert(&e)))),
                     ty.location(),
@@ -242,53 +659,124 @@ impl Runtime {
             }
             Type::List(inner) => {
                 Located::new(
-                    RuntimeType::List(Box::new(self.convert(inner))),
+                    RuntimeType::List(Arc::new(self.convert(inner))),
+                    ty.location(),
+                )
+            }
+            Type::Message(inner, message) => {
+                Located::new(
+                    RuntimeType::Message(
+                        Arc::new(self.convert(inner)),
+                        message.clone(),
+                    ),
                     ty.location(),
                 )
             }
             Type::Nothing => Located::new(RuntimeType::Nothing, ty.location())
         }
     }
+
+    /// Flatten nested joins or meets into a single list of terms.
+    fn convert_terms<'t>(
+        &self,
+        ty: &'t Located<Type>,
+        split: &impl Fn(&'t Type) -> Option<(&'t Located<Type>, &'t Located<Type>)>,
+    ) -> Vec<Arc<Located<RuntimeType>>> {
+        if let Some((lhs, rhs)) = split(ty) {
+            let mut terms = self.convert_terms(lhs, split);
+            terms.extend(self.convert_terms(rhs, split));
+            terms
+        } else {
+            vec![Arc::new(self.convert(ty))]
+        }
+    }
 }
 
 pub enum RuntimeType {
     Anything,
     Primordial(PrimordialType),
-    Ref(Arc<Runtime>, Located<TypeName>),
+    Ref(Located<TypeName>),
     Const(Located<Value>),
     Object(RuntimeObjectType),
     Expr(Arc<Located<Expr>>),
-    Join(Arc<Located<RuntimeType>>, Arc<Located<RuntimeType>>),
-    Meet(Arc<Located<RuntimeType>>, Arc<Located<RuntimeType>>),
-    Functional(Arc<Runtime>, Located<TypeName>, Option<Arc<Located<RuntimeType>>>),
-    List(Box<Located<RuntimeType>>),
+    Join(Vec<Arc<Located<RuntimeType>>>),
+    Meet(Vec<Arc<Located<RuntimeType>>>),
+    Functional(Located<TypeName>, Option<Arc<Located<RuntimeType>>>),
+    List(Arc<Located<RuntimeType>>),
+    Message(Arc<Located<RuntimeType>>, Located<String>),
     Nothing,
 }
 
+impl RuntimeType {
+    fn kind(&self) -> &'static str {
+        match self {
+            RuntimeType::Anything => "anything",
+            RuntimeType::Primordial(_) => "primordial",
+            RuntimeType::Ref(_) => "ref",
+            RuntimeType::Const(_) => "const",
+            RuntimeType::Object(_) => "object",
+            RuntimeType::Expr(_) => "expr",
+            RuntimeType::Join(_) => "join",
+            RuntimeType::Meet(_) => "meet",
+            RuntimeType::Functional(_, _) => "functional",
+            RuntimeType::List(_) => "list",
+            RuntimeType::Message(_, _) => "message",
+            RuntimeType::Nothing => "nothing",
+        }
+    }
+}
+
 impl Debug for RuntimeType {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             RuntimeType::Anything => write!(f, "anything"),
             RuntimeType::Primordial(inner) => write!(f, "{:?}", inner),
-            RuntimeType::Ref(_, name) => write!(f, "{}", name.as_type_str()),
+            RuntimeType::Ref(name) => write!(f, "{}", name.as_type_str()),
             RuntimeType::Const(inner) => write!(f, "{:?}", inner),
             RuntimeType::Object(inner) => write!(f, "{:?}", inner),
             RuntimeType::Expr(inner) => write!(f, "$({:?})", inner),
-            RuntimeType::Join(lhs, rhs) => write!(f, "({:?} || {:?})", lhs, rhs),
-            RuntimeType::Meet(lhs, rhs) => write!(f, "({:?} && {:?})", lhs, rhs),
-            RuntimeType::Functional(_, name, ty) => write!(f, "{:?}({:?})", name, ty),
+            RuntimeType::Join(terms) => write!(f, "({})", terms.iter().map(|e| format!("{:?}", e)).collect::<Vec<_>>().join(" || ")),
+            RuntimeType::Meet(terms) => write!(f, "({})", terms.iter().map(|e| format!("{:?}", e)).collect::<Vec<_>>().join(" && ")),
+            RuntimeType::Functional(name, ty) => write!(f, "{:?}({:?})", name, ty),
             RuntimeType::List(inner) => write!(f, "[{:?}]", inner),
+            RuntimeType::Message(inner, message) => write!(f, "{:?} else {:?}", inner, message),
             RuntimeType::Nothing => write!(f, "nothing"),
         }
     }
 }
 
 impl Located<RuntimeType> {
-    pub fn evaluate<'v>(self: &'v Arc<Self>, value: &'v mut RuntimeValue) -> Pin<Box<dyn Future<Output=Result<EvaluationResult, RuntimeError>> + 'v>> {
-        println!("eval self {:?}", self);
-        println!("vs");
-        println!("obj {:?}", value);
-        println!("");
+    pub(crate) fn evaluate<'v>(self: &'v Arc<Self>, value: &'v RuntimeValue, runtime: &'v Runtime, mode: EvaluationMode, budget: &'v Budget, observer: Option<&'v dyn Observer>, depth: usize) -> Pin<Box<dyn Future<Output=Result<EvaluationResult, RuntimeError>> + Send + 'v>> {
+        let span = tracing::trace_span!(
+            "evaluate",
+            kind = self.kind(),
+            span = ?self.span(),
+            depth,
+            outcome = tracing::field::Empty,
+        );
+        if span.is_disabled() && observer.is_none() {
+            return self.evaluate_node(value, runtime, mode, budget, observer, depth);
+        }
+        if let Some(observer) = observer {
+            observer.enter(self, value, depth);
+        }
+        let evaluation = self.evaluate_node(value, runtime, mode, budget, observer, depth);
+        Box::pin(
+            async move {
+                let result = evaluation.await;
+                tracing::Span::current().record("outcome", outcome(&result));
+                if let Some(observer) = observer {
+                    observer.leave(self, value, &result);
+                }
+                result
+            }.instrument(span)
+        )
+    }
+
+    fn evaluate_node<'v>(self: &'v Arc<Self>, value: &'v RuntimeValue, runtime: &'v Runtime, mode: EvaluationMode, budget: &'v Budget, observer: Option<&'v dyn Observer>, depth: usize) -> Pin<Box<dyn Future<Output=Result<EvaluationResult, RuntimeError>> + Send + 'v>> {
+        if let Err(err) = budget.charge(depth) {
+            return Box::pin(ready(Err(err)));
+        }
         match &***self {
             RuntimeType::Anything => {
                 return Box::pin(
@@ -298,219 +786,285 @@ impl Located<RuntimeType> {
                 );
             }
             RuntimeType::Primordial(inner) => {
-                println!("primordial");
-                match inner {
-                    PrimordialType::Integer => {
-                        if value.is_integer() {
-                            println!("prim A");
-                            value.note(self.clone(), true);
-                            return Box::pin(ready(Ok(EvaluationResult::new().set_value(value.clone()))));
-                        } else {
-                            println!("prim B");
-                            value.note(self.clone(), false);
-                            return Box::pin(ready(Ok(EvaluationResult::new())));
-                        }
-                    }
-                    PrimordialType::Decimal => {
-                        if value.is_decimal() {
-                            println!("prim C");
-                            value.note(self.clone(), true);
-                            return Box::pin(ready(Ok(EvaluationResult::new().set_value(value.clone()))));
-                        } else {
-                            println!("prim D");
-                            value.note(self.clone(), false);
-                            return Box::pin(ready(Ok(EvaluationResult::new())));
-                        }
-                    }
-                    PrimordialType::Boolean => {
-                        if value.is_boolean() {
-                            println!("prim E");
-                            value.note(self.clone(), true);
-                            return Box::pin(ready(Ok(EvaluationResult::new().set_value(value.clone()))));
-                        } else {
-                            println!("prim F");
-                            value.note(self.clone(), false);
-                            return Box::pin(ready(Ok(EvaluationResult::new())));
-                        }
-                    }
-                    PrimordialType::String => {
-                        if value.is_string() {
-                            println!("prim G");
-                            value.note(self.clone(), true);
-                            return Box::pin(ready(Ok(EvaluationResult::new().set_value(value.clone()))));
-                        } else {
-                            println!("prim H");
-                            value.note(self.clone(), false);
-                            return Box::pin(ready(Ok(EvaluationResult::new())));
-                        }
-                    }
+                let matched = match inner {
+                    PrimordialType::Integer => value.is_integer(),
+                    PrimordialType::Decimal => value.is_decimal(),
+                    PrimordialType::Boolean => value.is_boolean(),
+                    PrimordialType::String => value.is_string(),
                     PrimordialType::Function(name, func) => {
                         return Box::pin(async move {
-                            let mut result = func.call(value).await;
+                            let started = Instant::now();
+                            let result = budget.call(name, &**func, value).await?;
+                            if let Some(observer) = observer {
+                                observer.call(name, value, &result, started.elapsed());
+                            }
                             if let Ok(transform) = result {
-                                value.transform( name.clone(), transform.clone() );
-                                println!("fn call -> {:?}", transform);
-                                return Ok(EvaluationResult::new().set_value(transform.clone()));
+                                return Ok(EvaluationResult::new()
+                                    .set_value(transform.clone())
+                                    .add_annotations([Annotation::Transform(String::new(), name.clone(), transform)]));
                             } else {
-                                println!("fn call failed?");
                                 return Ok(EvaluationResult::new());
                             }
                         });
                     }
+                };
+                if matched {
+                    return Box::pin(ready(Ok(EvaluationResult::new().set_value(value.clone()).note_type(self, value, true, observer))));
+                } else {
+                    return Box::pin(ready(Ok(EvaluationResult::new().note_type(self, value, false, observer))));
                 }
             }
-            RuntimeType::Ref(runtime, path) => {
+            RuntimeType::Ref(path) => {
                 return Box::pin(
                     async move {
-                        let result = runtime.evaluate(path.as_type_str(), value).await;
-                        println!("REF RESULT {:?}", result);
-                        result
+                        runtime.evaluate_internal(path, value, mode, budget, observer, depth).await
                     }
                 );
             }
             RuntimeType::Const(inner) => {
-                println!("const");
                 if (**inner).eq(value) {
-                    value.note(self.clone(), true);
-                    println!("eq");
-                    return Box::pin(ready(Ok(EvaluationResult::new().set_value(value.clone()))));
+                    return Box::pin(ready(Ok(EvaluationResult::new().set_value(value.clone()).note_type(self, value, true, observer))));
                 } else {
-                    println!("not-eq");
-                    value.note(self.clone(), false);
-                    return Box::pin(ready(Ok(EvaluationResult::new())));
+                    return Box::pin(ready(Ok(EvaluationResult::new().note_type(self, value, false, observer))));
                 }
             }
             RuntimeType::Object(inner) => {
                 return Box::pin(async move {
-                    if value.is_object() {
-                        let mut obj = value.try_get_object();
-                        let mut mismatch = vec![];
-                        if let Some(obj) = obj {
-                            for field in &inner.fields {
-                                println!("check field {:?}", field);
-                                if let Some(field_value) = obj.get(field.name.clone().into_inner()) {
-                                    let result = field.ty.evaluate(field_value).await?;
-                                    println!("field result {:?}", result);
-                                    if result.value().is_none() {
-                                        value.note(self.clone(), false);
-                                        return Ok(EvaluationResult::new());
-                                    }
-                                } else {
-                                    mismatch.push(field);
-                                    break;
-                                }
+                    if let Some(obj) = value.try_get_object() {
+                        let mut matched = true;
+                        let mut messages = Vec::new();
+                        let mut annotations = Vec::new();
+                        let concurrency = runtime.concurrency(inner.fields.iter().map(|e| &e.ty), budget, observer);
+                        let evaluations = inner.fields.iter().map(|field| -> Evaluation<'v, _> {
+                            if let Some(field_value) = obj.get(&field.name) {
+                                Box::pin(async move {
+                                    let result = field.ty.evaluate(field_value, runtime, mode, budget, observer, depth + 1).await?;
+                                    Ok((field, Some(result)))
+                                })
+                            } else {
+                                Box::pin(ready(Ok((field, None))))
                             }
-                            if !mismatch.is_empty() {
-                                println!("mismatch obj");
-                                for e in mismatch {
-                                    value.note(e.clone(), false);
+                        }).collect();
+                        evaluate_in_order(evaluations, concurrency, |(field, result)| {
+                            if let Some(result) = result {
+                                annotations.extend(result.annotations_within(&field.name));
+                                if !result.matches() {
+                                    matched = false;
+                                    messages.extend_from_slice(result.messages());
                                 }
-                                value.note(self.clone(), false);
-                                return Ok(EvaluationResult::new());
                             } else {
-                                println!("match obj");
-                                value.note(self.clone(), true);
-                                return Ok(EvaluationResult::new().set_value(value.clone()));
+                                if let Some(observer) = observer {
+                                    observer.note(&field.clone().into(), value, false);
+                                }
+                                let mut missing = Annotation::Nonmatch(String::new(), field.clone().into());
+                                missing.rebase(&field.name);
+                                annotations.push(missing);
+                                matched = false;
                             }
+                            matched || mode == EvaluationMode::Exhaustive
+                        }).await?;
+                        if matched {
+                            Ok(EvaluationResult::new()
+                                .set_value(value.clone())
+                                .add_annotations(annotations)
+                                .note_type(self, value, true, observer))
                         } else {
-                            value.note(self.clone(), false);
-                            return Ok(EvaluationResult::new());
+                            Ok(EvaluationResult::new()
+                                .add_messages(&messages)
+                                .add_annotations(annotations)
+                                .note_type(self, value, false, observer))
                         }
                     } else {
-                        value.note(self.clone(), false);
-                        return Ok(EvaluationResult::new());
+                        Ok(EvaluationResult::new().note_type(self, value, false, observer))
                     }
                 });
             }
             RuntimeType::Expr(expr) => {
-                return Box::pin(
-                    async move {
-                        let result = expr.evaluate(value)?;
-                        if let Some(true) = result.try_get_boolean() {
-                            value.note(self.clone(), true);
-                            return Ok(EvaluationResult::new().set_value(value.clone()));
-                        } else {
-                            value.note(self.clone(), false);
-                            return Ok(EvaluationResult::new());
-                        }
-                    });
+                // expressions that fail to evaluate, such as arithmetic
+                // on a non-numeric value, do not match
+                let matched = matches!(expr.evaluate(value).map(|e| e.try_get_boolean()), Ok(Some(true)));
+                if matched {
+                    return Box::pin(ready(Ok(EvaluationResult::new().set_value(value.clone()).note_type(self, value, true, observer))));
+                } else {
+                    return Box::pin(ready(Ok(EvaluationResult::new().note_type(self, value, false, observer))));
+                }
             }
-            RuntimeType::Join(lhs, rhs) => {
+            RuntimeType::Join(terms) => {
                 return Box::pin(async move {
-                    let lhs_result = lhs.evaluate(value).await?;
-                    let rhs_result = rhs.evaluate(value).await?;
-
-                    if lhs_result.value().is_some() {
-                        value.note(lhs.clone(), true);
-                    }
-
-                    if rhs_result.value().is_some() {
-                        value.note(rhs.clone(), true);
+                    let mut matched = false;
+                    let mut messages = Vec::new();
+                    let mut annotations = Vec::new();
+
+                    for term in terms {
+                        let result = term.evaluate(value, runtime, mode, budget, observer, depth).await?;
+                        annotations.extend(result.annotations().iter().cloned());
+                        if result.matches() {
+                            annotations.push(Annotation::Match(String::new(), term.clone().into()));
+                            matched = true;
+                            if mode == EvaluationMode::Fast {
+                                break;
+                            }
+                        } else {
+                            messages.extend_from_slice(result.messages());
+                        }
                     }
 
-                    if rhs_result.value().is_some() || lhs_result.value().is_some() {
-                        return Ok(EvaluationResult::new().set_value(value.clone()));
+                    if matched {
+                        return Ok(EvaluationResult::new().set_value(value.clone()).add_annotations(annotations));
                     }
 
-                    return Ok(EvaluationResult::new());
+                    return Ok(EvaluationResult::new().add_messages(&messages).add_annotations(annotations));
                 });
             }
-            RuntimeType::Meet(lhs, rhs) => {
+            RuntimeType::Meet(terms) => {
                 return Box::pin(async move {
-                    let lhs_result = lhs.evaluate(value).await?;
-                    let rhs_result = rhs.evaluate(value).await?;
-
-                    if lhs_result.value().is_some() {
-                        value.note(lhs.clone(), true);
-                    }
-
-                    if rhs_result.value().is_some() {
-                        value.note(rhs.clone(), true);
+                    let mut matched = true;
+                    let mut messages = Vec::new();
+                    let mut annotations = Vec::new();
+
+                    for term in terms {
+                        let result = term.evaluate(value, runtime, mode, budget, observer, depth).await?;
+                        annotations.extend(result.annotations().iter().cloned());
+                        if result.matches() {
+                            annotations.push(Annotation::Match(String::new(), term.clone().into()));
+                        } else {
+                            matched = false;
+                            messages.extend_from_slice(result.messages());
+                            if mode == EvaluationMode::Fast {
+                                break;
+                            }
+                        }
                     }
 
-                    if rhs_result.value().is_some() && lhs_result.value().is_some() {
-                        return Ok(EvaluationResult::new().set_value(value.clone()));
+                    if matched {
+                        return Ok(EvaluationResult::new().set_value(value.clone()).add_annotations(annotations));
                     }
 
-                    return Ok(EvaluationResult::new());
+                    return Ok(EvaluationResult::new().add_messages(&messages).add_annotations(annotations));
                 });
             }
-            RuntimeType::Functional(runtime, path, ty) => {
+            RuntimeType::Functional(path, ty) => {
                 return Box::pin(
                     async move {
-                        let mut result = runtime.evaluate(path.as_type_str(), value).await?;
-                        println!("functional call result: {:?}", result);
-                        if let Some(fn_value) = &mut result.value_mut().as_mut() {
+                        let result = runtime.evaluate_internal(path, value, mode, budget, observer, depth).await?;
+                        // the inner type's annotations describe the output, not the value
+                        let annotations = result.annotations().iter().cloned();
+                        if let Some(fn_value) = result.value() {
                             if let Some(ty) = ty {
-                                println!("inner ty check {:?}", ty);
-                                let result = ty.evaluate(fn_value).await?;
-                                if result.value().is_some() {
-                                    println!("ITC A");
-                                    Ok(EvaluationResult::new().set_value(value.clone()))
+                                let inner = ty.evaluate(fn_value, runtime, mode, budget, observer, depth).await?;
+                                if inner.matches() {
+                                    Ok(EvaluationResult::new().set_value(value.clone()).add_annotations(annotations))
                                 } else {
-                                    println!("ITC B");
-                                    Ok(EvaluationResult::new())
+                                    Ok(EvaluationResult::new().add_messages(inner.messages()).add_annotations(annotations))
                                 }
                             } else {
-                                println!("no inner ty check");
-                                Ok(EvaluationResult::new().set_value(value.clone()))
+                                Ok(EvaluationResult::new().set_value(value.clone()).add_annotations(annotations))
                             }
                         } else {
-                            println!("failed fncall");
-                            return Ok(EvaluationResult::new());
+                            return Ok(EvaluationResult::new().add_annotations(annotations));
+                        }
+                    }
+                );
+            }
+            RuntimeType::List(inner) => {
+                return Box::pin(async move {
+                    if let Some(list) = value.try_get_list() {
+                        let mut matched = true;
+                        let mut messages = Vec::new();
+                        let mut annotations = Vec::new();
+                        let concurrency = runtime.concurrency([inner].into_iter(), budget, observer);
+                        let evaluations = list.iter().enumerate().map(|(index, element)| -> Evaluation<'v, _> {
+                            Box::pin(async move {
+                                let result = inner.evaluate(element, runtime, mode, budget, observer, depth + 1).await?;
+                                Ok((index, result))
+                            })
+                        }).collect();
+                        evaluate_in_order(evaluations, concurrency, |(index, result)| {
+                            annotations.extend(result.annotations_within(&index.to_string()));
+                            if !result.matches() {
+                                matched = false;
+                                messages.extend_from_slice(result.messages());
+                            }
+                            matched || mode == EvaluationMode::Exhaustive
+                        }).await?;
+                        if matched {
+                            Ok(EvaluationResult::new()
+                                .set_value(value.clone())
+                                .add_annotations(annotations)
+                                .note_type(self, value, true, observer))
+                        } else {
+                            Ok(EvaluationResult::new()
+                                .add_messages(&messages)
+                                .add_annotations(annotations)
+                                .note_type(self, value, false, observer))
+                        }
+                    } else {
+                        Ok(EvaluationResult::new().note_type(self, value, false, observer))
+                    }
+                });
+            }
+            RuntimeType::Message(inner, message) => {
+                return Box::pin(
+                    async move {
+                        let result = inner.evaluate(value, runtime, mode, budget, observer, depth).await?;
+                        if result.matches() {
+                            Ok(result)
+                        } else {
+                            // the policy author's message replaces any from within
+                            Ok(EvaluationResult::new()
+                                .add_messages(&[interpolate(message, value)])
+                                .add_annotations(result.annotations().iter().cloned()))
                         }
                     }
                 );
             }
-            RuntimeType::List(_) => {}
             RuntimeType::Nothing => {}
         }
 
-        println!("shit");
         Box::pin(ready(Ok(EvaluationResult::new())))
     }
 }
 
+fn outcome(result: &Result<EvaluationResult, RuntimeError>) -> &'static str {
+    match result {
+        Ok(result) if result.matches() => "match",
+        Ok(_) => "nonmatch",
+        Err(_) => "error",
+    }
+}
+
+type Evaluation<'v, T> = Pin<Box<dyn Future<Output=Result<T, RuntimeError>> + Send + 'v>>;
+
+/// Await `evaluations` in order, running up to `concurrency` of them at
+/// once, and hand each outcome to `each` until it returns false. Those
+/// still running then are abandoned.
+async fn evaluate_in_order<'v, T: Send>(
+    evaluations: Vec<Evaluation<'v, T>>,
+    concurrency: usize,
+    mut each: impl FnMut(T) -> bool + Send,
+) -> Result<(), RuntimeError> {
+    if concurrency <= 1 {
+        for evaluation in evaluations {
+            if !each(evaluation.await?) {
+                break;
+            }
+        }
+    } else {
+        let mut outcomes = stream::iter(evaluations).buffered(concurrency);
+        while let Some(outcome) = outcomes.next().await {
+            if !each(outcome?) {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Substitute `{self}` in a message with the value that failed to match.
+fn interpolate(message: &str, value: &RuntimeValue) -> String {
+    message.replace("{self}", &value.to_string())
+}
+
 #[derive(Debug)]
 pub enum PrimordialType {
     Integer,
@@ -527,16 +1081,29 @@ pub struct RuntimeObjectType {
 
 #[derive(Debug)]
 pub struct RuntimeField {
+    attributes: Vec<Located<Attribute>>,
     name: Located<String>,
     ty: Arc<Located<RuntimeType>>,
 }
 
+impl RuntimeField {
+    pub fn name(&self) -> Located<String> {
+        self.name.clone()
+    }
+
+    pub fn attributes(&self) -> &Vec<Located<Attribute>> {
+        &self.attributes
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::env;
     use std::iter::once;
+    use std::sync::atomic::{AtomicUsize, Ordering};
     use serde_json::json;
     use super::*;
+    use crate::function::Counting;
     use crate::runtime::sources::{Directory, Ephemeral};
 
     #[test]
@@ -570,7 +1137,7 @@ mod test {
     #[actix_rt::test]
     async fn evaluate_function() {
         let src = Ephemeral::new(PackagePath::from_parts(vec!["foo", "bar"]), r#"
-            type signed-thing = {
+            pub type signed-thing = {
                 digest: sigstore::SHA256( {
                     apiVersion: "0.0.1",
                 } ),
@@ -589,9 +1156,9 @@ mod test {
             }
         );
 
-        let mut value = (&value).into();
+        let value = (&value).into();
 
-        let result = runtime.evaluate("foo::bar::signed-thing".into(), &mut value).await;
+        let result = runtime.evaluate("foo::bar::signed-thing".into(), &value).await;
 
         println!("{:?}", result);
     }
@@ -609,7 +1176,7 @@ mod test {
             age: $(self > 52),
         }
 
-        type folks = bob || jim
+        pub type folks = bob || jim
 
         "#.into());
 
@@ -624,13 +1191,495 @@ mod test {
             }
         );
 
-        println!("{:?}", good_bob);
+        let good_bob = (&good_bob).into();
 
-        let mut good_bob = (&good_bob).into();
+        let result = runtime.evaluate("foo::bar::folks".into(), &good_bob).await;
+        assert!(result.unwrap().matches());
+    }
 
-        let result = runtime.evaluate("foo::bar::folks".into(), &mut good_bob).await;
-        println!("{:?}", result);
+    #[actix_rt::test]
+    async fn evaluate_private_type() {
+        let src = Ephemeral::new(PackagePath::from_parts(vec!["foo", "bar"]), r#"
+        type bob = {
+            name: "Bob",
+        }
+
+        pub type folks = bob
+        "#.into());
+
+        let mut builder = Builder::new();
+        builder.build(src.iter()).unwrap();
+        let runtime = builder.link().unwrap();
+
+        let value = (&json!({ "name": "Bob" })).into();
+        let result = runtime.evaluate("foo::bar::bob".into(), &value).await;
+        assert!(matches!(result, Err(RuntimeError::NoSuchType(_))));
 
-        println!("{:?}", good_bob);
+        let value = (&json!({ "name": "Bob" })).into();
+        let result = runtime.evaluate("foo::bar::folks".into(), &value).await;
+        assert!(result.unwrap().matches());
     }
-}
\ No newline at end of file
+
+    #[actix_rt::test]
+    async fn evaluate_messages() {
+        let src = Ephemeral::new(PackagePath::from_parts(vec!["foo", "bar"]), r#"
+        pub type deployment = {
+            replicas: $(self > 0) else "replicas must be positive, not {self}",
+        }
+        "#.into());
+
+        let mut builder = Builder::new();
+        builder.build(src.iter()).unwrap();
+        let runtime = builder.link().unwrap();
+
+        let value = (&json!({ "replicas": 3 })).into();
+        let result = runtime.evaluate("foo::bar::deployment".into(), &value).await.unwrap();
+        assert!(result.matches());
+        assert!(result.messages().is_empty());
+
+        let value = (&json!({ "replicas": 0 })).into();
+        let result = runtime.evaluate("foo::bar::deployment".into(), &value).await.unwrap();
+        assert!(!result.matches());
+        assert_eq!(result.messages(), &vec!["replicas must be positive, not 0".to_string()]);
+    }
+
+    #[test]
+    fn query_attributes() {
+        let src = Ephemeral::new(PackagePath::from_parts(vec!["foo", "bar"]), r#"
+        #[severity(high)]
+        #[owners(alice, bob)]
+        pub type bob = {
+            name: "Bob",
+        }
+        "#.into());
+
+        let mut builder = Builder::new();
+        builder.build(src.iter()).unwrap();
+        let runtime = builder.link().unwrap();
+
+        let attributes = runtime.attributes("foo::bar::bob".into()).unwrap();
+        assert_eq!(attributes.len(), 2);
+        assert_eq!(&*attributes[0].name(), "severity");
+        assert_eq!(attributes[0].arg(), Some("high".into()));
+        assert_eq!(attributes[1].args().len(), 2);
+    }
+
+    #[test]
+    fn query_field_attributes() {
+        let src = Ephemeral::new(PackagePath::from_parts(vec!["foo", "bar"]), r#"
+        type person = {
+            #[description("as on their passport")]
+            name: "Bob",
+            age: int,
+        }
+        pub type bob = person else "not a person"
+        pub type jim = int
+        "#.into());
+
+        let mut builder = Builder::new();
+        builder.build(src.iter()).unwrap();
+        let runtime = builder.link().unwrap();
+
+        let fields = runtime.fields("foo::bar::bob".into()).unwrap();
+        assert_eq!(fields.iter().map(|e| e.name().into_inner()).collect::<Vec<_>>(), vec!["name", "age"]);
+        assert_eq!(&*fields[0].attributes()[0].name(), "description");
+        assert_eq!(fields[0].attributes()[0].arg(), Some("as on their passport".into()));
+        assert!(fields[1].attributes().is_empty());
+
+        assert!(runtime.fields("foo::bar::jim".into()).unwrap().is_empty());
+        assert!(matches!(runtime.fields("foo::bar::person".into()), Err(RuntimeError::NoSuchType(_))));
+    }
+
+    #[test]
+    fn link_deprecated_type_warns() {
+        let src = Ephemeral::new(PackagePath::from_parts(vec!["foo", "bar"]), r#"
+        #[deprecated("use bob-v2")]
+        pub type bob
+
+        pub type jim = bob
+        "#.into());
+
+        let mut builder = Builder::new();
+        builder.build(src.iter()).unwrap();
+        let runtime = builder.link().unwrap();
+
+        assert!(matches!(
+            runtime.warnings().as_slice(),
+            [BuildWarning::Deprecated(_, name, Some(message))]
+                if name.name() == "bob" && message == "use bob-v2"
+        ));
+    }
+
+    #[test]
+    fn link_deprecated_use_warns() {
+        let bar = Ephemeral::new(PackagePath::from_parts(vec!["foo", "bar"]), r#"
+        #[deprecated("use bob-v2")]
+        pub type bob
+        "#.into());
+
+        let baz = Ephemeral::new(PackagePath::from_parts(vec!["foo", "baz"]), r#"
+        use foo::bar::bob
+
+        pub type jim = bob
+        pub type fred = bob
+        "#.into());
+
+        let mut builder = Builder::new();
+        builder.build(bar.iter().chain(baz.iter())).unwrap();
+        let runtime = builder.link().unwrap();
+
+        assert!(matches!(
+            runtime.warnings().as_slice(),
+            [BuildWarning::Deprecated(source, name, Some(message))]
+                if source.to_string() == "foo/baz" && name.span() == (13..26) && message == "use bob-v2"
+        ));
+    }
+
+    #[test]
+    fn link_private_type_from_other_package() {
+        let bar = Ephemeral::new(PackagePath::from_parts(vec!["foo", "bar"]), r#"
+        type bob
+        "#.into());
+
+        let baz = Ephemeral::new(PackagePath::from_parts(vec!["foo", "baz"]), r#"
+        use foo::bar::bob
+
+        pub type jim = bob
+        "#.into());
+
+        let mut builder = Builder::new();
+        builder.build(bar.iter().chain(baz.iter())).unwrap();
+        let result = builder.link();
+
+        assert!(matches!(
+            result.err().as_deref(),
+            Some([BuildError::TypeNotVisible(_, _), BuildError::TypeNotVisible(_, _)])
+        ));
+    }
+
+    fn shared(first: &Runtime, second: &Runtime, path: &str) -> bool {
+        let path = TypeName::from(path.to_string());
+        Arc::ptr_eq(
+            &first.types[&path].ty,
+            &second.types[&path].ty,
+        )
+    }
+
+    #[actix_rt::test]
+    async fn relink_changed_sources() {
+        let bar = PackagePath::from_parts(vec!["foo", "bar"]);
+        let baz = PackagePath::from_parts(vec!["foo", "baz"]);
+        let quux = PackagePath::from_parts(vec!["foo", "quux"]);
+
+        let mut builder = Builder::new();
+        builder.build(
+            Ephemeral::new(bar.clone(), "pub type bob = int".into()).iter()
+                .chain(Ephemeral::new(baz.clone(), "pub type anne = 42".into()).iter())
+                .chain(Ephemeral::new(quux, "use foo::bar::bob\npub type fred = bob".into()).iter())
+        ).unwrap();
+        let first = builder.relink().unwrap();
+
+        builder.build(Ephemeral::new(bar, "pub type bob = 42".into()).iter()).unwrap();
+        let second = builder.relink().unwrap();
+
+        assert!(shared(&first, &second, "foo::baz::anne"));
+        assert!(!shared(&first, &second, "foo::bar::bob"));
+        assert!(!shared(&first, &second, "foo::quux::fred"));
+
+        let value = (&json!(41)).into();
+        assert!(!second.evaluate("foo::quux::fred".into(), &value).await.unwrap().matches());
+        let value = (&json!(42)).into();
+        assert!(second.evaluate("foo::quux::fred".into(), &value).await.unwrap().matches());
+
+        builder.remove(&Source::from(baz));
+        let third = builder.relink().unwrap();
+
+        assert!(shared(&second, &third, "foo::quux::fred"));
+        let value = (&json!(42)).into();
+        assert!(matches!(
+            third.evaluate("foo::baz::anne".into(), &value).await,
+            Err(RuntimeError::NoSuchType(_))
+        ));
+    }
+
+    #[test]
+    fn relink_removed_source_still_referenced() {
+        let bar = PackagePath::from_parts(vec!["foo", "bar"]);
+        let baz = PackagePath::from_parts(vec!["foo", "baz"]);
+
+        let mut builder = Builder::new();
+        builder.build(
+            Ephemeral::new(bar.clone(), "pub type x = int".into()).iter()
+                .chain(Ephemeral::new(baz, "use foo::bar::x\npub type y = x".into()).iter())
+        ).unwrap();
+        builder.relink().unwrap();
+
+        builder.remove(&Source::from(bar));
+        let result = builder.relink();
+
+        assert!(matches!(
+            result.err().as_deref(),
+            Some([BuildError::TypeNotFound(source, name)])
+                if source.to_string() == "foo/baz" && name.as_type_str() == "::foo::bar::x"
+        ));
+    }
+
+    #[test]
+    fn evaluate_on_another_thread() {
+        let src = Ephemeral::new(PackagePath::from_parts(vec!["foo", "bar"]), r#"
+            pub type bob = {
+                name: "Bob",
+                digest: base64::Base64(),
+            }
+        "#.into());
+
+        let mut builder = Builder::new();
+        builder.add_function_package(PackagePath::from_parts(vec!["base64"]), crate::function::base64::package());
+        builder.build(src.iter()).unwrap();
+        let runtime = builder.link().unwrap();
+
+        // built here, but driven to completion elsewhere
+        let evaluation = async move {
+            let value = (&json!({ "name": "Bob", "digest": "aGVsbG8=" })).into();
+            runtime.evaluate("foo::bar::bob".into(), &value).await
+        };
+
+        let result = std::thread::spawn(move || {
+            actix_rt::System::new().block_on(evaluation)
+        }).join().unwrap();
+
+        assert!(result.unwrap().matches());
+    }
+
+    /// Matches anything, once polled a second time.
+    #[derive(Debug)]
+    struct Yielding;
+
+    impl Function for Yielding {
+        fn call<'v>(&'v self, input: &'v Value) -> Pin<Box<dyn Future<Output=Result<Value, ()>> + Send + 'v>> {
+            let mut yielded = false;
+            Box::pin(std::future::poll_fn(move |cx| {
+                if yielded {
+                    Poll::Ready(Ok(input.clone()))
+                } else {
+                    yielded = true;
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                }
+            }))
+        }
+    }
+
+    struct ActixExecutor;
+
+    impl Executor for ActixExecutor {
+        fn block_on<'f>(&self, evaluation: Pin<Box<dyn Future<Output=Result<EvaluationResult, RuntimeError>> + Send + 'f>>) -> Result<EvaluationResult, RuntimeError> {
+            actix_rt::System::new().block_on(evaluation)
+        }
+    }
+
+    #[test]
+    fn evaluate_blocking() {
+        let src = Ephemeral::new(PackagePath::from_parts(vec!["foo", "bar"]), r#"
+            pub type pure = {
+                name: "Bob",
+                digest: base64::Base64(),
+            }
+            pub type impure = {
+                name: "Bob",
+                digest: test::Yielding(),
+            }
+        "#.into());
+
+        let mut test = FunctionPackage::new();
+        test.register("Yielding".into(), Yielding);
+
+        let mut builder = Builder::new();
+        builder.add_function_package(PackagePath::from_parts(vec!["base64"]), crate::function::base64::package());
+        builder.add_function_package(PackagePath::from_parts(vec!["test"]), test);
+        builder.build(src.iter()).unwrap();
+        let runtime = builder.link().unwrap();
+
+        let input = (&json!({ "name": "Bob", "digest": "aGVsbG8=" })).into();
+
+        assert!(runtime.evaluate_blocking("foo::bar::pure".into(), &input).unwrap().matches());
+
+        assert!(matches!(
+            runtime.evaluate_blocking("foo::bar::impure".into(), &input),
+            Err(RuntimeError::RequiresExecutor(name)) if name == "::test::Yielding"
+        ));
+
+        assert!(runtime.evaluate_with("foo::bar::impure".into(), &input, &ActixExecutor).unwrap().matches());
+    }
+
+    fn paths(annotations: &[Annotation]) -> Vec<(&str, bool)> {
+        annotations.iter()
+            .filter_map(|e| match e {
+                Annotation::Match(path, _) => Some((path.as_str(), true)),
+                Annotation::Nonmatch(path, _) => Some((path.as_str(), false)),
+                Annotation::Transform(_, _, _) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn evaluate_without_mutation() {
+        let src = Ephemeral::new(PackagePath::from_parts(vec!["foo", "bar"]), r#"
+            pub type deployment = {
+                replicas: $(self > 0),
+                image: base64::Base64(),
+            }
+            pub type scaled = {
+                replicas: $(self > 2),
+            }
+        "#.into());
+
+        let mut builder = Builder::new();
+        builder.add_function_package(PackagePath::from_parts(vec!["base64"]), crate::function::base64::package());
+        builder.build(src.iter()).unwrap();
+        let runtime = builder.link().unwrap();
+
+        let value: Value = (&json!({ "replicas": 1, "image": "aGVsbG8=" })).into();
+        let before = value.to_string();
+
+        let deployment = runtime.evaluate_blocking("foo::bar::deployment".into(), &value).unwrap();
+        let scaled = runtime.evaluate_blocking("foo::bar::scaled".into(), &value).unwrap();
+        assert_eq!(value.to_string(), before);
+
+        assert!(deployment.matches());
+        assert_eq!(paths(deployment.annotations()), vec![("/replicas", true), ("", true)]);
+        assert!(matches!(
+            deployment.annotations().iter().find(|e| matches!(e, Annotation::Transform(_, _, _))),
+            Some(Annotation::Transform(path, name, output)) if path == "/image" && name.as_type_str() == "::base64::Base64" && output.to_string() == "<5 octets>"
+        ));
+
+        assert!(!scaled.matches());
+        assert_eq!(paths(scaled.annotations()), vec![("/replicas", false), ("", false)]);
+    }
+
+    #[test]
+    fn evaluation_modes() {
+        let src = Ephemeral::new(PackagePath::from_parts(vec!["foo", "bar"]), r#"
+            pub type deployment = {
+                replicas: $(self > 0) else "replicas must be positive",
+                image: "nginx" else "image must be nginx",
+                ports: [$(self < 1024) else "port {self} is unprivileged"],
+            }
+            pub type either = int || test::Counting()
+            pub type both = "Bob" && test::Counting()
+        "#.into());
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut test = FunctionPackage::new();
+        test.register("Counting".into(), Counting(calls.clone()));
+
+        let mut builder = Builder::new();
+        builder.add_function_package(PackagePath::from_parts(vec!["test"]), test);
+        builder.build(src.iter()).unwrap();
+        let runtime = builder.link().unwrap();
+
+        let value = (&json!({ "replicas": 0, "image": "redis", "ports": [80, 8080, 9090] })).into();
+
+        let fast = runtime.evaluate_blocking_in("foo::bar::deployment".into(), &value, EvaluationMode::Fast).unwrap();
+        assert_eq!(fast.messages(), &vec!["replicas must be positive".to_string()]);
+
+        let exhaustive = runtime.evaluate_blocking_in("foo::bar::deployment".into(), &value, EvaluationMode::Exhaustive).unwrap();
+        assert!(!exhaustive.matches());
+        assert_eq!(exhaustive.messages(), &vec![
+            "replicas must be positive".to_string(),
+            "image must be nginx".to_string(),
+            "port 8080 is unprivileged".to_string(),
+            "port 9090 is unprivileged".to_string(),
+        ]);
+
+        let calls_in = |path: &str, value: serde_json::Value, mode| {
+            let before = calls.load(Ordering::SeqCst);
+            runtime.evaluate_blocking_in(path.into(), &(&value).into(), mode).unwrap();
+            calls.load(Ordering::SeqCst) - before
+        };
+
+        assert_eq!(calls_in("foo::bar::either", json!(42), EvaluationMode::Fast), 0);
+        assert_eq!(calls_in("foo::bar::either", json!(42), EvaluationMode::Exhaustive), 1);
+        assert_eq!(calls_in("foo::bar::both", json!("Jim"), EvaluationMode::Fast), 0);
+        assert_eq!(calls_in("foo::bar::both", json!("Jim"), EvaluationMode::Exhaustive), 1);
+    }
+
+    /// Matches even integers after a while, recording the most calls
+    /// ever in flight at once.
+    #[derive(Debug, Default)]
+    struct Slow {
+        in_flight: Arc<AtomicUsize>,
+        peak: Arc<AtomicUsize>,
+    }
+
+    impl Function for Slow {
+        fn call<'v>(&'v self, input: &'v Value) -> Pin<Box<dyn Future<Output=Result<Value, ()>> + Send + 'v>> {
+            Box::pin(async move {
+                let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                self.peak.fetch_max(in_flight, Ordering::SeqCst);
+                futures_timer::Delay::new(Duration::from_millis(50)).await;
+                self.in_flight.fetch_sub(1, Ordering::SeqCst);
+                match input.try_get_integer() {
+                    Some(even) if even % 2 == 0 => Ok(input.clone()),
+                    _ => Err(()),
+                }
+            })
+        }
+    }
+
+    #[actix_rt::test]
+    async fn concurrent_evaluation() {
+        let link = |concurrency| {
+            let src = Ephemeral::new(PackagePath::from_parts(vec!["foo", "bar"]), r#"
+                pub type numbers = [test::Slow() else "{self} is odd"]
+                pub type pair = { a: test::Slow(), b: test::Slow() }
+            "#.into());
+
+            let slow = Slow::default();
+            let peak = slow.peak.clone();
+            let mut test = FunctionPackage::new();
+            test.register("Slow".into(), slow);
+            let mut limits = Limits::default();
+            limits.set_concurrency(concurrency);
+
+            let mut builder = Builder::new();
+            builder.add_function_package(PackagePath::from_parts(vec!["test"]), test);
+            builder.set_limits(limits);
+            builder.build(src.iter()).unwrap();
+            (builder.link().unwrap(), peak)
+        };
+        let paths = |result: &EvaluationResult| {
+            result.annotations().iter().map(|e| e.path().to_string()).collect::<Vec<_>>()
+        };
+
+        let value = (&json!((0..10).collect::<Vec<_>>())).into();
+
+        let (runtime, peak) = link(1);
+        let sequential = runtime.evaluate_in("foo::bar::numbers".into(), &value, EvaluationMode::Exhaustive).await.unwrap();
+        assert_eq!(peak.load(Ordering::SeqCst), 1);
+
+        let (runtime, peak) = link(10);
+        let concurrent = runtime.evaluate_in("foo::bar::numbers".into(), &value, EvaluationMode::Exhaustive).await.unwrap();
+        assert!(peak.load(Ordering::SeqCst) > 1);
+
+        assert_eq!(concurrent.messages(), &vec![
+            "1 is odd".to_string(),
+            "3 is odd".to_string(),
+            "5 is odd".to_string(),
+            "7 is odd".to_string(),
+            "9 is odd".to_string(),
+        ]);
+        assert_eq!(concurrent.messages(), sequential.messages());
+        assert_eq!(paths(&concurrent), paths(&sequential));
+
+        // the first failure in order decides, however quickly later ones finish
+        let (runtime, _) = link(10);
+        let fast = runtime.evaluate("foo::bar::numbers".into(), &value).await.unwrap();
+        assert_eq!(fast.messages(), &vec!["1 is odd".to_string()]);
+
+        let value = (&json!({ "a": 2, "b": 4 })).into();
+        let (runtime, peak) = link(10);
+        assert!(runtime.evaluate("foo::bar::pair".into(), &value).await.unwrap().matches());
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+    }
+}
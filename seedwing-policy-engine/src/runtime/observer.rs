@@ -0,0 +1,116 @@
+//! Hooks into each step of an evaluation, from which audit logs,
+//! profilers, coverage tools or step debuggers can be built.
+
+use std::sync::Arc;
+use std::time::Duration;
+use crate::lang::Located;
+use crate::lang::ty::TypeName;
+use crate::runtime::{EvaluationResult, RuntimeError, RuntimeType};
+use crate::value::{Noted, Value};
+
+/// Told about an evaluation as it happens. Every method does nothing
+/// unless implemented.
+///
+/// Each `enter` is paired with a `leave` once the type's outcome is known,
+/// unless the evaluation is abandoned first. In between come the steps
+/// of its inner types.
+pub trait Observer: Send + Sync {
+    /// The type `name` is about to be evaluated, either as the type asked
+    /// for or by reference from another.
+    fn enter_type(&self, _name: &TypeName) {}
+
+    /// The type `name` has been evaluated.
+    fn leave_type(&self, _name: &TypeName, _result: &Result<EvaluationResult, RuntimeError>) {}
+
+    /// `ty` is about to be evaluated against `value`, found `depth` deep
+    /// within the evaluated value.
+    fn enter(&self, _ty: &Arc<Located<RuntimeType>>, _value: &Value, _depth: usize) {}
+
+    /// `ty` has been evaluated against `value`.
+    fn leave(&self, _ty: &Arc<Located<RuntimeType>>, _value: &Value, _result: &Result<EvaluationResult, RuntimeError>) {}
+
+    /// The function `name` was called with `input`, taking `elapsed`.
+    fn call(&self, _name: &TypeName, _input: &Value, _output: &Result<Value, ()>, _elapsed: Duration) {}
+
+    /// Whether `noted` matched `value`, as also recorded in the result's
+    /// annotations.
+    fn note(&self, _noted: &Noted, _value: &Value, _matches: bool) {}
+
+    /// Whether the steps of an evaluation must not overlap, such as to keep
+    /// a stack of the types being evaluated. Otherwise the fields and
+    /// elements of a value may be evaluated concurrently.
+    fn sequential(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use serde_json::json;
+    use crate::lang::Located;
+    use crate::lang::ty::{PackagePath, TypeName};
+    use crate::runtime::sources::Ephemeral;
+    use crate::runtime::{Builder, EvaluationMode, EvaluationResult, RuntimeError, RuntimeType};
+    use crate::value::{Noted, Value};
+    use super::Observer;
+
+    #[derive(Default)]
+    struct Recorder {
+        events: Mutex<Vec<String>>,
+    }
+
+    impl Observer for Recorder {
+        fn enter(&self, ty: &Arc<Located<RuntimeType>>, _value: &Value, depth: usize) {
+            self.events.lock().unwrap().push(format!("enter {} {:?}", depth, ty));
+        }
+
+        fn leave(&self, ty: &Arc<Located<RuntimeType>>, _value: &Value, result: &Result<EvaluationResult, RuntimeError>) {
+            let matches = matches!(result, Ok(result) if result.matches());
+            self.events.lock().unwrap().push(format!("leave {:?} {}", ty, matches));
+        }
+
+        fn call(&self, name: &TypeName, input: &Value, output: &Result<Value, ()>, _elapsed: Duration) {
+            self.events.lock().unwrap().push(format!("call {} {} {}", name.as_type_str(), input, output.is_ok()));
+        }
+
+        fn note(&self, noted: &Noted, value: &Value, matches: bool) {
+            if let Noted::Field(field) = noted {
+                self.events.lock().unwrap().push(format!("note {} {} {}", field.name.as_str(), value, matches));
+            }
+        }
+    }
+
+    #[actix_rt::test]
+    async fn observe_evaluation() {
+        let src = Ephemeral::new(PackagePath::from_parts(vec!["foo", "bar"]), r#"
+            pub type person = {
+                age: int,
+                digest: base64::Base64(),
+            }
+        "#.into());
+
+        let mut builder = Builder::new();
+        builder.add_function_package(PackagePath::from_parts(vec!["base64"]), crate::function::base64::package());
+        builder.build(src.iter()).unwrap();
+        let runtime = builder.link().unwrap();
+
+        let recorder = Recorder::default();
+        let value = (&json!({ "age": 42, "digest": "Ym9i" })).into();
+        let result = runtime.evaluate_observed("foo::bar::person".into(), &value, EvaluationMode::Exhaustive, &recorder).await;
+        assert!(result.unwrap().matches());
+
+        let events = recorder.events.into_inner().unwrap();
+        assert_eq!(events.iter().filter(|e| e.starts_with("enter")).count(), events.iter().filter(|e| e.starts_with("leave")).count());
+        assert!(events.first().unwrap().starts_with("enter 0"));
+        assert!(events.last().unwrap().ends_with("true"));
+        assert!(events.contains(&r#"call ::base64::Base64 "Ym9i" true"#.to_string()));
+
+        let recorder = Recorder::default();
+        let value = (&json!({ "age": 42 })).into();
+        let result = runtime.evaluate_observed("foo::bar::person".into(), &value, EvaluationMode::Exhaustive, &recorder).await;
+        assert!(!result.unwrap().matches());
+        assert!(recorder.events.into_inner().unwrap().contains(&"note digest {age: 42} false".to_string()));
+    }
+}
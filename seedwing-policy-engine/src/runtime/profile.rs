@@ -0,0 +1,315 @@
+//! Where evaluation time goes, per type and per function.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use serde_json::json;
+use crate::lang::ty::TypeName;
+use crate::runtime::{EvaluationResult, RuntimeError};
+use crate::runtime::observer::Observer;
+use crate::value::Value;
+
+/// An [`Observer`] timing each type evaluated and each function called.
+///
+/// A profiler follows one evaluation at a time, evaluating its steps
+/// sequentially. To profile concurrent evaluations, give each its own and
+/// [`Profile::merge`] the results.
+#[derive(Default)]
+pub struct Profiler {
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    stack: Vec<Frame>,
+    profile: Profile,
+}
+
+struct Frame {
+    name: String,
+    started: Instant,
+    children: Duration,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// What has been recorded so far.
+    pub fn profile(&self) -> Profile {
+        self.state.lock().unwrap().profile.clone()
+    }
+}
+
+impl Observer for Profiler {
+    fn enter_type(&self, name: &TypeName) {
+        self.state.lock().unwrap().stack.push(Frame {
+            name: name.as_type_str(),
+            started: Instant::now(),
+            children: Duration::ZERO,
+        });
+    }
+
+    fn leave_type(&self, name: &TypeName, _result: &Result<EvaluationResult, RuntimeError>) {
+        let mut state = self.state.lock().unwrap();
+        let name = name.as_type_str();
+
+        // frames of abandoned evaluations never left
+        let frame = loop {
+            match state.stack.pop() {
+                Some(frame) if frame.name == name => break frame,
+                Some(_) => {}
+                None => return,
+            }
+        };
+
+        let elapsed = frame.started.elapsed();
+        let self_time = elapsed.saturating_sub(frame.children);
+        let recursive = state.stack.iter().any(|e| e.name == name);
+        let stack = state.stack.iter()
+            .map(|e| e.name.as_str())
+            .chain([name.as_str()])
+            .collect::<Vec<_>>()
+            .join(";");
+
+        if let Some(parent) = state.stack.last_mut() {
+            parent.children += elapsed;
+        }
+
+        let profile = &mut state.profile;
+        *profile.stacks.entry(stack).or_default() += self_time;
+        let entry = profile.types.entry(name).or_default();
+        entry.calls += 1;
+        entry.self_time += self_time;
+        // the outermost evaluation of a recursive type already covers the rest
+        if !recursive {
+            entry.total += elapsed;
+        }
+    }
+
+    fn call(&self, name: &TypeName, _input: &Value, output: &Result<Value, ()>, elapsed: Duration) {
+        let mut state = self.state.lock().unwrap();
+        let function = state.profile.types
+            .entry(name.as_type_str())
+            .or_default()
+            .function
+            .get_or_insert_with(Default::default);
+
+        function.calls += 1;
+        if output.is_err() {
+            function.failures += 1;
+        }
+        function.total += elapsed;
+        function.max = function.max.max(elapsed);
+    }
+
+    fn sequential(&self) -> bool {
+        true
+    }
+}
+
+/// Timings recorded by a [`Profiler`], keyed by fully-qualified type name.
+#[derive(Clone, Debug, Default)]
+pub struct Profile {
+    types: HashMap<String, TypeProfile>,
+    stacks: HashMap<String, Duration>,
+}
+
+impl Profile {
+    pub fn get(&self, name: &str) -> Option<&TypeProfile> {
+        self.types.get(name)
+    }
+
+    pub fn types(&self) -> impl Iterator<Item=(&str, &TypeProfile)> {
+        self.types.iter().map(|(name, profile)| (name.as_str(), profile))
+    }
+
+    /// Add the timings of another profile, such as one from a concurrent
+    /// evaluation.
+    pub fn merge(&mut self, other: &Profile) {
+        for (name, profile) in &other.types {
+            self.types.entry(name.clone()).or_default().merge(profile);
+        }
+        for (stack, self_time) in &other.stacks {
+            *self.stacks.entry(stack.clone()).or_default() += *self_time;
+        }
+    }
+
+    /// Self time in microseconds per stack of types, one `a;b;c 42` line
+    /// each, as consumed by flamegraph tools.
+    pub fn to_folded(&self) -> String {
+        let mut stacks = self.stacks.iter().collect::<Vec<_>>();
+        stacks.sort();
+        stacks.iter()
+            .map(|(stack, self_time)| format!("{} {}\n", stack, self_time.as_micros()))
+            .collect()
+    }
+
+    /// Timings in microseconds per type, ordered by name.
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut types = self.types.iter().collect::<Vec<_>>();
+        types.sort_by_key(|(name, _)| *name);
+        let types = types.iter()
+            .map(|(name, profile)| {
+                let mut entry = json!({
+                    "name": name,
+                    "calls": profile.calls,
+                    "total_us": profile.total.as_micros() as u64,
+                    "self_us": profile.self_time.as_micros() as u64,
+                });
+                if let Some(function) = &profile.function {
+                    entry["function"] = json!({
+                        "calls": function.calls,
+                        "failures": function.failures,
+                        "total_us": function.total.as_micros() as u64,
+                        "max_us": function.max.as_micros() as u64,
+                    });
+                }
+                entry
+            })
+            .collect::<Vec<_>>();
+
+        json!({ "types": types })
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct TypeProfile {
+    calls: u64,
+    total: Duration,
+    self_time: Duration,
+    function: Option<FunctionProfile>,
+}
+
+impl TypeProfile {
+    /// How many times the type was evaluated.
+    pub fn calls(&self) -> u64 {
+        self.calls
+    }
+
+    /// Time spent evaluating the type, including the types it references.
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+
+    /// Time spent evaluating the type, excluding the types it references.
+    pub fn self_time(&self) -> Duration {
+        self.self_time
+    }
+
+    /// Call latency, if the type is a function.
+    pub fn function(&self) -> Option<&FunctionProfile> {
+        self.function.as_ref()
+    }
+
+    fn merge(&mut self, other: &TypeProfile) {
+        self.calls += other.calls;
+        self.total += other.total;
+        self.self_time += other.self_time;
+        if let Some(other) = &other.function {
+            let function = self.function.get_or_insert_with(Default::default);
+            function.calls += other.calls;
+            function.failures += other.failures;
+            function.total += other.total;
+            function.max = function.max.max(other.max);
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct FunctionProfile {
+    calls: u64,
+    failures: u64,
+    total: Duration,
+    max: Duration,
+}
+
+impl FunctionProfile {
+    pub fn calls(&self) -> u64 {
+        self.calls
+    }
+
+    /// Calls which returned no value.
+    pub fn failures(&self) -> u64 {
+        self.failures
+    }
+
+    pub fn total(&self) -> Duration {
+        self.total
+    }
+
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    pub fn mean(&self) -> Duration {
+        if self.calls == 0 {
+            Duration::ZERO
+        } else {
+            self.total / self.calls as u32
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+    use crate::lang::ty::PackagePath;
+    use crate::runtime::sources::Ephemeral;
+    use crate::runtime::{Builder, EvaluationMode};
+    use super::Profiler;
+
+    #[actix_rt::test]
+    async fn profile_types_and_functions() {
+        let src = Ephemeral::new(PackagePath::from_parts(vec!["foo", "bar"]), r#"
+            pub type people = [person]
+            type person = {
+                age: int,
+                digest: base64::Base64(),
+            }
+        "#.into());
+
+        let mut builder = Builder::new();
+        builder.add_function_package(PackagePath::from_parts(vec!["base64"]), crate::function::base64::package());
+        builder.build(src.iter()).unwrap();
+        let runtime = builder.link().unwrap();
+
+        let profiler = Profiler::new();
+        let value = (&json!([
+            { "age": 42, "digest": "Ym9i" },
+            { "age": 43, "digest": "not base64!" },
+        ])).into();
+        let result = runtime.evaluate_observed("foo::bar::people".into(), &value, EvaluationMode::Exhaustive, &profiler).await;
+        assert!(!result.unwrap().matches());
+
+        let profile = profiler.profile();
+        let people = profile.get("::foo::bar::people").unwrap();
+        assert_eq!(people.calls(), 1);
+        assert!(people.self_time() <= people.total());
+        assert_eq!(profile.get("::foo::bar::person").unwrap().calls(), 2);
+
+        let base64 = profile.get("::base64::Base64").unwrap().function().unwrap();
+        assert_eq!(base64.calls(), 2);
+        assert_eq!(base64.failures(), 1);
+
+        let folded = profile.to_folded();
+        let stacks = folded.lines()
+            .map(|line| line.rsplit_once(' ').unwrap().0)
+            .collect::<Vec<_>>();
+        assert_eq!(stacks, vec![
+            "::foo::bar::people",
+            "::foo::bar::people;::foo::bar::person",
+            "::foo::bar::people;::foo::bar::person;::base64::Base64",
+            "::foo::bar::people;::foo::bar::person;int",
+        ]);
+
+        let mut merged = profile.clone();
+        merged.merge(&profile);
+        assert_eq!(merged.get("::foo::bar::person").unwrap().calls(), 4);
+
+        let json = profile.to_json();
+        assert_eq!(json["types"][0]["name"], "::base64::Base64");
+        assert_eq!(json["types"][0]["function"]["failures"], 1);
+    }
+}
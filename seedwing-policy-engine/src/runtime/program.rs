@@ -0,0 +1,567 @@
+//! Linked runtimes lowered into a flat program for high-volume evaluation.
+//!
+//! References are resolved to instruction indices when compiling, and
+//! instructions reaching no [`Function`] are checked synchronously against
+//! the value, allocating only to report messages when they fail.
+
+use std::collections::{HashMap, HashSet};
+use std::future::{Future, ready};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+use crate::function::Function;
+use crate::lang::expr::Expr;
+use crate::lang::Located;
+use crate::lang::ty::TypeName;
+use crate::runtime::limits::{Budget, Limits};
+use crate::runtime::{interpolate, private_type_hint, EvaluationMode, EvaluationResult, PrimordialType, Runtime, RuntimeError, RuntimeType};
+use crate::value::{Annotation, Value};
+
+type NodeId = usize;
+
+type Evaluation<'p> = Pin<Box<dyn Future<Output=Result<bool, RuntimeError>> + Send + 'p>>;
+
+enum Instr {
+    Anything,
+    Nothing,
+    Integer,
+    Decimal,
+    Boolean,
+    String,
+    Const(Value),
+    Expr(Arc<Located<Expr>>),
+    Object(Vec<(String, NodeId)>),
+    List(NodeId),
+    Join(Vec<NodeId>),
+    Meet(Vec<NodeId>),
+    Message(NodeId, String),
+    Function(TypeName, Arc<dyn Function>),
+    /// A functional type, whose inner type checks the callee's output.
+    Call(NodeId, Option<NodeId>),
+    /// A definition which is a bare reference to another.
+    Alias(NodeId),
+    Missing(TypeName),
+}
+
+impl Instr {
+    fn children(&self) -> Vec<NodeId> {
+        match self {
+            Instr::Object(fields) => fields.iter().map(|(_, ty)| *ty).collect(),
+            Instr::Join(terms) | Instr::Meet(terms) => terms.clone(),
+            Instr::List(inner) | Instr::Message(inner, _) | Instr::Alias(inner) => vec![*inner],
+            Instr::Call(callee, inner) => std::iter::once(*callee).chain(*inner).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// A [`Runtime`] compiled by [`Runtime::compile`].
+pub struct Program {
+    instrs: Vec<Instr>,
+    pure: Vec<bool>,
+    entries: HashMap<TypeName, NodeId>,
+    private: HashSet<TypeName>,
+    limits: Limits,
+}
+
+impl Program {
+    pub(crate) fn compile(runtime: &Runtime) -> Self {
+        let mut compiler = Compiler {
+            instrs: Vec::new(),
+            slots: HashMap::new(),
+        };
+
+        // reserve a slot for each definition first, so that references,
+        // including recursive ones, resolve to it directly
+        for name in runtime.types.keys() {
+            compiler.slots.insert(name.clone(), compiler.instrs.len());
+            compiler.instrs.push(Instr::Nothing);
+        }
+
+        for (name, defn) in &runtime.types {
+            let instr = compiler.lower_instr(&defn.ty);
+            let slot = compiler.slots[name];
+            compiler.instrs[slot] = instr;
+        }
+
+        let entries = runtime.types.iter()
+            .filter(|(_, defn)| defn.visibility.is_public())
+            .map(|(name, _)| (name.clone(), compiler.slots[name]))
+            .collect();
+
+        let private = runtime.types.iter()
+            .filter(|(_, defn)| !defn.visibility.is_public())
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        let pure = purity(&compiler.instrs);
+
+        Self {
+            instrs: compiler.instrs,
+            pure,
+            entries,
+            private,
+            limits: runtime.limits.clone(),
+        }
+    }
+
+    /// Evaluate a value against a public type, as [`Runtime::evaluate`]
+    /// does in [`EvaluationMode::Fast`].
+    pub async fn evaluate(&self, path: String, value: &Value) -> Result<EvaluationResult, RuntimeError> {
+        let root = self.entry(path)?;
+        let mut report = Report::new(&self.limits);
+        let matched = self.run(root, value, &mut report, 0).await?;
+        Ok(report.outcome(matched, value))
+    }
+
+    /// Evaluate a value against a public type without an executor, as
+    /// [`Runtime::evaluate_blocking`].
+    pub fn evaluate_blocking(&self, path: String, value: &Value) -> Result<EvaluationResult, RuntimeError> {
+        let root = self.entry(path)?;
+        let mut report = Report::new(&self.limits);
+
+        let matched = if self.pure[root] {
+            self.check(root, value, &mut report, 0)?
+        } else {
+            let mut evaluation = self.run(root, value, &mut report, 0);
+            match evaluation.as_mut().poll(&mut Context::from_waker(Waker::noop())) {
+                Poll::Ready(matched) => matched?,
+                Poll::Pending => {
+                    let name = self.async_function(root).map(|e| e.as_type_str()).unwrap_or_default();
+                    return Err(RuntimeError::RequiresExecutor(name));
+                }
+            }
+        };
+
+        Ok(report.outcome(matched, value))
+    }
+
+    fn entry(&self, path: String) -> Result<NodeId, RuntimeError> {
+        let path = TypeName::from(path);
+        self.entries
+            .get(&path)
+            .copied()
+            .ok_or_else(|| {
+                if self.private.contains(&path) {
+                    private_type_hint(&path);
+                }
+                RuntimeError::NoSuchType(path.as_type_str())
+            })
+    }
+
+    /// Check a node reaching no functions. Messages from within are only
+    /// retained where the tree evaluator would report them.
+    fn check(&self, id: NodeId, value: &Value, report: &mut Report, depth: usize) -> Result<bool, RuntimeError> {
+        report.budget.charge(depth)?;
+        let matched = match &self.instrs[id] {
+            Instr::Anything => true,
+            Instr::Nothing => false,
+            Instr::Integer => value.is_integer(),
+            Instr::Decimal => value.is_decimal(),
+            Instr::Boolean => value.is_boolean(),
+            Instr::String => value.is_string(),
+            Instr::Const(expected) => *expected == *value,
+            Instr::Expr(expr) => matches!(expr.evaluate(value).map(|e| e.try_get_boolean()), Ok(Some(true))),
+            Instr::Object(fields) => match value.try_get_object() {
+                Some(obj) => {
+                    for (name, ty) in fields {
+                        match obj.get(name) {
+                            Some(field) if self.check(*ty, field, report, depth + 1)? => {}
+                            _ => return Ok(false),
+                        }
+                    }
+                    true
+                }
+                None => false,
+            },
+            Instr::List(inner) => match value.try_get_list() {
+                Some(elements) => {
+                    for element in elements {
+                        if !self.check(*inner, element, report, depth + 1)? {
+                            return Ok(false);
+                        }
+                    }
+                    true
+                }
+                None => false,
+            },
+            Instr::Join(terms) => {
+                let mark = report.messages.len();
+                for term in terms {
+                    if self.check(*term, value, report, depth)? {
+                        report.messages.truncate(mark);
+                        return Ok(true);
+                    }
+                }
+                false
+            }
+            Instr::Meet(terms) => {
+                for term in terms {
+                    if !self.check(*term, value, report, depth)? {
+                        return Ok(false);
+                    }
+                }
+                true
+            }
+            Instr::Message(inner, message) => {
+                let mark = report.messages.len();
+                let matched = self.check(*inner, value, report, depth)?;
+                if !matched {
+                    report.messages.truncate(mark);
+                    report.messages.push(interpolate(message, value));
+                }
+                matched
+            }
+            Instr::Call(callee, inner) => {
+                // a pure callee is a type, whose output is the value itself
+                let mark = report.messages.len();
+                if !self.check(*callee, value, report, depth)? {
+                    report.messages.truncate(mark);
+                    return Ok(false);
+                }
+                match inner {
+                    Some(inner) => self.check(*inner, value, report, depth)?,
+                    None => true,
+                }
+            }
+            Instr::Alias(target) => self.check(*target, value, report, depth)?,
+            // functions are never pure, so only reached by a miscompiled program
+            Instr::Function(name, _) => return Err(RuntimeError::RequiresExecutor(name.as_type_str())),
+            Instr::Missing(name) => return Err(RuntimeError::NoSuchType(name.as_type_str())),
+        };
+
+        Ok(matched)
+    }
+
+    /// Evaluate any node, recording function outputs as annotations.
+    fn run<'p>(&'p self, id: NodeId, value: &'p Value, report: &'p mut Report, depth: usize) -> Evaluation<'p> {
+        if self.pure[id] {
+            return Box::pin(ready(self.check(id, value, report, depth)));
+        }
+
+        Box::pin(async move {
+            report.budget.charge(depth)?;
+            let matched = match &self.instrs[id] {
+                Instr::Object(fields) => {
+                    let Some(obj) = value.try_get_object() else {
+                        return Ok(false);
+                    };
+                    for (name, ty) in fields {
+                        let Some(field) = obj.get(name) else {
+                            return Ok(false);
+                        };
+                        let mark = report.annotations.len();
+                        let matched = self.run(*ty, field, report, depth + 1).await?;
+                        report.rebase(mark, name);
+                        if !matched {
+                            return Ok(false);
+                        }
+                    }
+                    true
+                }
+                Instr::List(inner) => match value.try_get_list() {
+                    Some(elements) => {
+                        for (index, element) in elements.iter().enumerate() {
+                            let mark = report.annotations.len();
+                            let matched = self.run(*inner, element, report, depth + 1).await?;
+                            report.rebase(mark, &index.to_string());
+                            if !matched {
+                                return Ok(false);
+                            }
+                        }
+                        true
+                    }
+                    None => false,
+                },
+                Instr::Join(terms) => {
+                    let mark = report.messages.len();
+                    for term in terms {
+                        if self.run(*term, value, report, depth).await? {
+                            report.messages.truncate(mark);
+                            return Ok(true);
+                        }
+                    }
+                    false
+                }
+                Instr::Meet(terms) => {
+                    for term in terms {
+                        if !self.run(*term, value, report, depth).await? {
+                            return Ok(false);
+                        }
+                    }
+                    true
+                }
+                Instr::Message(inner, message) => {
+                    let mark = report.messages.len();
+                    let matched = self.run(*inner, value, report, depth).await?;
+                    if !matched {
+                        report.messages.truncate(mark);
+                        report.messages.push(interpolate(message, value));
+                    }
+                    matched
+                }
+                Instr::Function(name, func) => {
+                    if let Ok(output) = report.budget.call(name, &**func, value).await? {
+                        report.annotations.push(Annotation::Transform(String::new(), name.clone(), output));
+                        true
+                    } else {
+                        false
+                    }
+                }
+                Instr::Call(callee, inner) => {
+                    if let Instr::Function(name, func) = &self.instrs[self.resolve(*callee)] {
+                        let Ok(output) = report.budget.call(name, &**func, value).await? else {
+                            return Ok(false);
+                        };
+                        // the inner type's annotations describe the output, not the value
+                        let matched = match inner {
+                            Some(inner) => {
+                                let mark = report.annotations.len();
+                                let matched = self.run(*inner, &output, report, depth).await?;
+                                report.annotations.truncate(mark);
+                                matched
+                            }
+                            None => true,
+                        };
+                        report.annotations.push(Annotation::Transform(String::new(), name.clone(), output));
+                        matched
+                    } else {
+                        let mark = report.messages.len();
+                        if !self.run(*callee, value, report, depth).await? {
+                            report.messages.truncate(mark);
+                            return Ok(false);
+                        }
+                        match inner {
+                            Some(inner) => self.run(*inner, value, report, depth).await?,
+                            None => true,
+                        }
+                    }
+                }
+                Instr::Alias(target) => self.run(*target, value, report, depth).await?,
+                _ => self.check(id, value, report, depth)?,
+            };
+
+            Ok(matched)
+        })
+    }
+
+    fn resolve(&self, mut id: NodeId) -> NodeId {
+        while let Instr::Alias(target) = &self.instrs[id] {
+            id = *target;
+        }
+        id
+    }
+
+    fn async_function(&self, root: NodeId) -> Option<&TypeName> {
+        let mut visited = vec![false; self.instrs.len()];
+        let mut pending = vec![root];
+
+        while let Some(next) = pending.pop() {
+            if std::mem::replace(&mut visited[next], true) {
+                continue;
+            }
+            if let Instr::Function(name, func) = &self.instrs[next] {
+                if func.is_async() {
+                    return Some(name);
+                }
+            }
+            pending.extend(self.instrs[next].children());
+        }
+
+        None
+    }
+}
+
+/// Messages from failing nodes and annotations, which unlike the tree
+/// evaluator are only recorded for function outputs.
+struct Report {
+    messages: Vec<String>,
+    annotations: Vec<Annotation>,
+    budget: Budget,
+}
+
+impl Report {
+    fn new(limits: &Limits) -> Self {
+        Self {
+            messages: Vec::new(),
+            annotations: Vec::new(),
+            budget: Budget::new(limits),
+        }
+    }
+
+    fn rebase(&mut self, mark: usize, segment: &str) {
+        for annotation in &mut self.annotations[mark..] {
+            annotation.rebase(segment);
+        }
+    }
+
+    fn outcome(self, matched: bool, value: &Value) -> EvaluationResult {
+        let result = if matched {
+            EvaluationResult::new().set_value(value.clone())
+        } else {
+            EvaluationResult::new().add_messages(&self.messages)
+        };
+        result.add_annotations(self.annotations)
+    }
+}
+
+/// Nodes are pure unless they are, or reach, a function.
+fn purity(instrs: &[Instr]) -> Vec<bool> {
+    let mut pure = instrs.iter()
+        .map(|e| !matches!(e, Instr::Function(_, _)))
+        .collect::<Vec<_>>();
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (id, instr) in instrs.iter().enumerate() {
+            if pure[id] && instr.children().iter().any(|e| !pure[*e]) {
+                pure[id] = false;
+                changed = true;
+            }
+        }
+    }
+
+    pure
+}
+
+struct Compiler {
+    instrs: Vec<Instr>,
+    slots: HashMap<TypeName, NodeId>,
+}
+
+impl Compiler {
+    fn lower(&mut self, ty: &Located<RuntimeType>) -> NodeId {
+        if let RuntimeType::Ref(path) = &**ty {
+            return self.slot(path);
+        }
+        let instr = self.lower_instr(ty);
+        self.instrs.push(instr);
+        self.instrs.len() - 1
+    }
+
+    fn slot(&mut self, path: &TypeName) -> NodeId {
+        if let Some(slot) = self.slots.get(path) {
+            *slot
+        } else {
+            self.instrs.push(Instr::Missing(path.clone()));
+            self.instrs.len() - 1
+        }
+    }
+
+    fn lower_instr(&mut self, ty: &Located<RuntimeType>) -> Instr {
+        match &**ty {
+            RuntimeType::Anything => Instr::Anything,
+            RuntimeType::Nothing => Instr::Nothing,
+            RuntimeType::Primordial(inner) => match inner {
+                PrimordialType::Integer => Instr::Integer,
+                PrimordialType::Decimal => Instr::Decimal,
+                PrimordialType::Boolean => Instr::Boolean,
+                PrimordialType::String => Instr::String,
+                PrimordialType::Function(name, func) => Instr::Function(name.clone(), func.clone()),
+            },
+            RuntimeType::Ref(path) => Instr::Alias(self.slot(path)),
+            RuntimeType::Const(inner) => Instr::Const((**inner).clone()),
+            RuntimeType::Object(inner) => Instr::Object(
+                inner.fields.iter()
+                    .map(|field| (field.name.clone().into_inner(), self.lower(&field.ty)))
+                    .collect()
+            ),
+            RuntimeType::Expr(expr) => Instr::Expr(expr.clone()),
+            RuntimeType::Join(terms) => Instr::Join(terms.iter().map(|e| self.lower(e)).collect()),
+            RuntimeType::Meet(terms) => Instr::Meet(terms.iter().map(|e| self.lower(e)).collect()),
+            RuntimeType::Functional(path, inner) => {
+                let callee = self.slot(path);
+                Instr::Call(callee, inner.as_ref().map(|e| self.lower(e)))
+            }
+            RuntimeType::List(inner) => Instr::List(self.lower(inner)),
+            RuntimeType::Message(inner, message) => Instr::Message(self.lower(inner), message.clone().into_inner()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+    use crate::lang::ty::PackagePath;
+    use crate::runtime::sources::Ephemeral;
+    use crate::runtime::{Builder, Runtime, RuntimeError};
+    use crate::value::Value;
+    use std::sync::Arc;
+
+    fn link(src: &str) -> Arc<Runtime> {
+        let src = Ephemeral::new(PackagePath::from_parts(vec!["foo", "bar"]), src.into());
+        let mut builder = Builder::new();
+        builder.add_function_package(PackagePath::from_parts(vec!["base64"]), crate::function::base64::package());
+        builder.build(src.iter()).unwrap();
+        builder.link().unwrap()
+    }
+
+    #[test]
+    fn compiled_parity() {
+        let runtime = link(r#"
+            type replicas = $(self * 2 >= 4 && self != 10) else "bad replica count {self}"
+            type name = "web" || "api" || $(self == "db") else "unknown name"
+            type container = {
+                image: "nginx" || "envoy",
+                ports: [int],
+            }
+            pub type deployment = {
+                name: name,
+                replicas: replicas,
+                containers: [container],
+            }
+            pub type tree = int || { children: [tree] }
+        "#);
+        let program = runtime.compile();
+
+        let values = [
+            ("foo::bar::deployment", json!({ "name": "web", "replicas": 3, "containers": [ { "image": "nginx", "ports": [80] } ] })),
+            ("foo::bar::deployment", json!({ "name": "db", "replicas": 1, "containers": [] })),
+            ("foo::bar::deployment", json!({ "name": "mail", "replicas": 10, "containers": [] })),
+            ("foo::bar::deployment", json!({ "name": "api", "replicas": 2, "containers": [ { "image": "redis", "ports": [] } ] })),
+            ("foo::bar::deployment", json!({ "name": "api", "replicas": 2 })),
+            ("foo::bar::deployment", json!({ "name": "api", "replicas": "two", "containers": [] })),
+            ("foo::bar::deployment", json!(42)),
+            ("foo::bar::tree", json!({ "children": [ 1, { "children": [ 2, 3 ] } ] })),
+            ("foo::bar::tree", json!({ "children": [ 1, { "children": [ "three" ] } ] })),
+        ];
+
+        for (path, value) in values {
+            let tree_value: Value = (&value).into();
+            let program_value: Value = (&value).into();
+            let expected = runtime.evaluate_blocking(path.into(), &tree_value).unwrap();
+            let actual = program.evaluate_blocking(path.into(), &program_value).unwrap();
+
+            assert_eq!(actual.matches(), expected.matches(), "{} against {}", path, value);
+            assert_eq!(actual.messages(), expected.messages(), "{} against {}", path, value);
+        }
+
+        let value = (&json!({ "name": "db", "replicas": 1, "containers": [] })).into();
+        let result = program.evaluate_blocking("foo::bar::deployment".into(), &value).unwrap();
+        assert_eq!(result.messages(), &vec!["bad replica count 1".to_string()]);
+
+        assert!(matches!(
+            program.evaluate_blocking("foo::bar::container".into(), &value),
+            Err(RuntimeError::NoSuchType(_))
+        ));
+    }
+
+    #[actix_rt::test]
+    async fn compiled_functions() {
+        let runtime = link(r#"
+            pub type signed = {
+                name: "Bob",
+                digest: base64::Base64(),
+            }
+        "#);
+        let program = runtime.compile();
+
+        let value = (&json!({ "name": "Bob", "digest": "aGVsbG8=" })).into();
+        assert!(program.evaluate("foo::bar::signed".into(), &value).await.unwrap().matches());
+        assert!(program.evaluate_blocking("foo::bar::signed".into(), &value).unwrap().matches());
+
+        let value = (&json!({ "name": "Jim", "digest": "aGVsbG8=" })).into();
+        assert!(!program.evaluate("foo::bar::signed".into(), &value).await.unwrap().matches());
+    }
+}
@@ -1,5 +1,6 @@
 use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
 use std::rc::Rc;
 use std::sync::Arc;
 use crate::function::Function;
@@ -35,17 +36,46 @@ impl From<Arc<Located<Expr>>> for Noted {
     }
 }
 
+/// Something learned about part of an evaluated value, which is located
+/// by a JSON Pointer such as `/containers/0/image`.
+#[derive(Debug, Clone)]
+pub enum Annotation {
+    Match(String, Noted),
+    Nonmatch(String, Noted),
+    /// The output of a function called with the value.
+    Transform(String, TypeName, Value),
+}
+
+impl Annotation {
+    pub fn path(&self) -> &str {
+        match self {
+            Annotation::Match(path, _)
+            | Annotation::Nonmatch(path, _)
+            | Annotation::Transform(path, _, _) => path,
+        }
+    }
+
+    /// Re-base onto the field or element `segment` of an enclosing value.
+    pub(crate) fn rebase(&mut self, segment: &str) {
+        let path = match self {
+            Annotation::Match(path, _)
+            | Annotation::Nonmatch(path, _)
+            | Annotation::Transform(path, _, _) => path,
+        };
+        path.insert_str(0, &format!("/{}", segment.replace('~', "~0").replace('/', "~1")));
+    }
+}
+
+/// Clones share structure; the `try_get_*` accessors returning mutable
+/// references copy it first if shared.
 #[derive(Debug, Clone)]
 pub struct Value {
-    inner: InnerValue,
-    matches: Vec<Noted>,
-    nonmatches: Vec<Noted>,
-    transforms: HashMap<TypeName, Box<Value>>,
+    inner: Arc<InnerValue>,
 }
 
 impl PartialEq<Self> for Value {
     fn eq(&self, other: &Self) -> bool {
-        match (&self.inner, &other.inner)  {
+        match (&*self.inner, &*other.inner)  {
             (InnerValue::Boolean(lhs), InnerValue::Boolean(rhs)) => {
                 lhs == rhs
             }
@@ -65,7 +95,7 @@ impl PartialEq<Self> for Value {
 
 impl PartialOrd for Value {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        match (&self.inner, &other.inner) {
+        match (&*self.inner, &*other.inner) {
             (InnerValue::Boolean(lhs), InnerValue::Boolean(rhs)) => {
                 lhs.partial_cmp(rhs)
             }
@@ -125,17 +155,91 @@ impl From<Vec<Value>> for Value {
     }
 }
 
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &*self.inner {
+            InnerValue::Null => write!(f, "null"),
+            InnerValue::String(inner) => write!(f, "{:?}", inner),
+            InnerValue::Integer(inner) => write!(f, "{}", inner),
+            InnerValue::Decimal(inner) => write!(f, "{}", inner),
+            InnerValue::Boolean(inner) => write!(f, "{}", inner),
+            InnerValue::Object(inner) => {
+                let mut fields = inner.fields.iter().collect::<Vec<_>>();
+                fields.sort_by_key(|(name, _)| *name);
+                write!(f, "{{")?;
+                for (i, (name, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", name, value)?;
+                }
+                write!(f, "}}")
+            }
+            InnerValue::List(inner) => {
+                write!(f, "[")?;
+                for (i, value) in inner.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            InnerValue::Octets(inner) => write!(f, "<{} octets>", inner.len()),
+        }
+    }
+}
+
 impl Value {
+    /// Identifies the value exactly: unlike its `Display`, decimals are
+    /// distinct from integers and octets are compared by content.
+    pub(crate) fn canonical(&self) -> String {
+        let mut canonical = String::new();
+        self.write_canonical(&mut canonical);
+        canonical
+    }
 
+    fn write_canonical(&self, out: &mut String) {
+        match &*self.inner {
+            InnerValue::Null => out.push_str("null"),
+            InnerValue::String(inner) => out.push_str(&format!("{:?}", inner)),
+            InnerValue::Integer(inner) => out.push_str(&inner.to_string()),
+            InnerValue::Decimal(inner) => out.push_str(&format!("{:?}", inner)),
+            InnerValue::Boolean(inner) => out.push_str(&inner.to_string()),
+            InnerValue::Object(inner) => {
+                let mut fields = inner.fields.iter().collect::<Vec<_>>();
+                fields.sort_by_key(|(name, _)| *name);
+                out.push('{');
+                for (name, value) in fields {
+                    out.push_str(&format!("{:?}:", name));
+                    value.write_canonical(out);
+                    out.push(',');
+                }
+                out.push('}');
+            }
+            InnerValue::List(inner) => {
+                out.push('[');
+                for value in inner {
+                    value.write_canonical(out);
+                    out.push(',');
+                }
+                out.push(']');
+            }
+            InnerValue::Octets(inner) => {
+                out.push('<');
+                for octet in inner {
+                    out.push_str(&format!("{:02x}", octet));
+                }
+                out.push('>');
+            }
+        }
+    }
 }
 
 impl From<InnerValue> for Value {
     fn from(inner: InnerValue) -> Self {
         Self {
-            inner,
-            matches: vec![],
-            nonmatches: vec![],
-            transforms: Default::default()
+            inner: Arc::new(inner),
         }
     }
 }
@@ -153,27 +257,15 @@ pub enum InnerValue {
 }
 
 impl Value {
-    pub(crate) fn note<N: Into<Noted>>(&mut self, noted: N, matches: bool) {
-        if matches {
-            self.matches.push(noted.into());
-        } else {
-            self.nonmatches.push(noted.into());
-        }
-    }
-
-    pub(crate) fn transform(&mut self, name: TypeName, value: Value) {
-        self.transforms.insert( name, Box::new(value));
-    }
-
     pub fn is_string(&self) -> bool {
-        match &self.inner {
+        match &*self.inner {
             InnerValue::String(_) => true,
             _ => false,
         }
     }
 
     pub fn try_get_string(&self) -> Option<String> {
-        if let InnerValue::String(inner) = &self.inner {
+        if let InnerValue::String(inner) = &*self.inner {
             Some(inner.clone())
         } else {
             None
@@ -181,14 +273,14 @@ impl Value {
     }
 
     pub fn is_integer(&self) -> bool {
-        match &self.inner {
+        match &*self.inner {
             InnerValue::Integer(_) => true,
             _ => false,
         }
     }
 
     pub fn try_get_integer(&self) -> Option<i64> {
-        if let InnerValue::Integer(inner) = &self.inner {
+        if let InnerValue::Integer(inner) = &*self.inner {
             Some(*inner)
         } else {
             None
@@ -196,14 +288,14 @@ impl Value {
     }
 
     pub fn is_decimal(&self) -> bool {
-        match &self.inner {
+        match &*self.inner {
             InnerValue::Decimal(_) => true,
             _ => false,
         }
     }
 
     pub fn try_get_decimal(&self) -> Option<f64> {
-        if let InnerValue::Decimal(inner) = &self.inner {
+        if let InnerValue::Decimal(inner) = &*self.inner {
             Some(*inner)
         } else {
             None
@@ -211,14 +303,14 @@ impl Value {
     }
 
     pub fn is_boolean(&self) -> bool {
-        match &self.inner {
+        match &*self.inner {
             InnerValue::Boolean(_) => true,
             _ => false,
         }
     }
 
     pub fn try_get_boolean(&self) -> Option<bool> {
-        if let InnerValue::Boolean(inner) = &self.inner {
+        if let InnerValue::Boolean(inner) = &*self.inner {
             Some(*inner)
         } else {
             None
@@ -226,14 +318,25 @@ impl Value {
     }
 
     pub fn is_list(&self) -> bool {
-        match &self.inner {
+        match &*self.inner {
             InnerValue::List(_) => true,
             _ => false
         }
     }
 
-    pub fn try_get_list(&mut self) -> Option<&mut Vec<Value>> {
-        if let InnerValue::List(inner) = &mut self.inner {
+    pub fn try_get_list(&self) -> Option<&Vec<Value>> {
+        if let InnerValue::List(inner) = &*self.inner {
+            Some(inner)
+        } else {
+            None
+        }
+    }
+
+    pub fn try_get_list_mut(&mut self) -> Option<&mut Vec<Value>> {
+        if !self.is_list() {
+            return None;
+        }
+        if let InnerValue::List(inner) = Arc::make_mut(&mut self.inner) {
             Some(inner)
         } else {
             None
@@ -241,14 +344,25 @@ impl Value {
     }
 
     pub fn is_object(&self) -> bool {
-        match &self.inner {
+        match &*self.inner {
             InnerValue::Object(_) => true,
             _ => false,
         }
     }
 
-    pub fn try_get_object(&mut self) -> Option<&mut Object> {
-        if let InnerValue::Object(inner) = &mut self.inner {
+    pub fn try_get_object(&self) -> Option<&Object> {
+        if let InnerValue::Object(inner) = &*self.inner {
+            Some(inner)
+        } else {
+            None
+        }
+    }
+
+    pub fn try_get_object_mut(&mut self) -> Option<&mut Object> {
+        if !self.is_object() {
+            return None;
+        }
+        if let InnerValue::Object(inner) = Arc::make_mut(&mut self.inner) {
             Some(inner)
         } else {
             None
@@ -263,9 +377,34 @@ pub struct Object {
 
 impl Object {
 
-    pub fn get(&mut self, name: String) -> Option<&mut Value> {
-        self.fields.get_mut(&name)
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.fields.get(name)
+    }
 
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Value> {
+        self.fields.get_mut(name)
     }
 
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+    use super::Value;
+
+    #[test]
+    fn copy_on_write() {
+        let original: Value = (&json!({ "spec": { "replicas": 1 } })).into();
+        let mut copy = original.clone();
+
+        let replicas = copy.try_get_object_mut()
+            .and_then(|e| e.get_mut("spec"))
+            .and_then(|e| e.try_get_object_mut())
+            .and_then(|e| e.get_mut("replicas"))
+            .unwrap();
+        *replicas = 3.into();
+
+        assert_eq!(original.to_string(), "{spec: {replicas: 1}}");
+        assert_eq!(copy.to_string(), "{spec: {replicas: 3}}");
+    }
+}
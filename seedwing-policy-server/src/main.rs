@@ -10,6 +10,7 @@ use env_logger::Builder;
 use log::LevelFilter;
 
 use seedwing_policy_engine::runtime::{Builder as PolicyBuilder, Runtime};
+use seedwing_policy_engine::runtime::handle::RuntimeHandle;
 use seedwing_policy_engine::runtime::sources::Directory;
 use crate::policy::evaluate;
 
@@ -18,18 +19,26 @@ async fn main() -> std::io::Result<()> {
     Builder::new()
         .filter_level(LevelFilter::Warn)
         .filter_module("seedwing_proxy", LevelFilter::max())
+        // RUST_LOG, e.g. `seedwing_policy_engine=debug`, overrides the above
+        .parse_default_env()
         .init();
 
     let src = Directory::new( env::current_dir()?.join( "policy") );
 
-    println!("loading {:?}", src);
+    log::info!("loading {:?}", src);
     let mut builder = PolicyBuilder::new();
     let result = builder.build(src.iter());
     let runtime = builder.link().unwrap();
 
+    for warning in runtime.warnings() {
+        log::warn!("{:?}", warning);
+    }
+
+    let runtime = web::Data::new(RuntimeHandle::new(runtime));
+
     let server = HttpServer::new(move || {
         App::new()
-            .app_data(web::Data::new(runtime.clone()))
+            .app_data(runtime.clone())
             .default_service( web::to(evaluate) )
     });
 
@@ -1,19 +1,19 @@
 use std::future::{Future, poll_fn};
 use std::pin::Pin;
-use std::sync::Arc;
 use std::task::{Context, Poll};
 use actix_web::dev::{HttpServiceFactory, Service};
 use actix_web::{HttpRequest, HttpMessage, Responder, web, post, HttpResponse, Handler};
 use actix_web::http::Method;
 use actix_web::web::{BytesMut, Payload};
 use actix_web::web::service;
-use seedwing_policy_engine::runtime::{EvaluationResult, Runtime, RuntimeError};
+use seedwing_policy_engine::runtime::{EvaluationResult, RuntimeError};
+use seedwing_policy_engine::runtime::handle::RuntimeHandle;
 use seedwing_policy_engine::value;
 use futures_util::stream::StreamExt;
 use serde_json::json;
 use seedwing_policy_engine::value::Value;
 
-pub async fn evaluate(runtime: web::Data<Arc<Runtime>>, mut req: HttpRequest, mut body: Payload) -> impl Responder {
+pub async fn evaluate(runtime: web::Data<RuntimeHandle>, mut req: HttpRequest, mut body: Payload) -> impl Responder {
 
     if req.method() != Method::POST {
         return HttpResponse::NotAcceptable().finish();
@@ -28,16 +28,20 @@ pub async fn evaluate(runtime: web::Data<Arc<Runtime>>, mut req: HttpRequest, mu
     let result: Result<serde_json::Value, _> = serde_json::from_slice( &*content);
 
     if let Ok(result) = &result {
-        let mut value = Value::from(result);
+        let value = Value::from(result);
         let path = req.path().strip_prefix("/").unwrap().replace("/", "::");
 
-        println!("{} {:?}", path, value);
-        match runtime.evaluate( path, &mut value ).await {
+        log::debug!("evaluating {}", path);
+        match runtime.evaluate( path, &value ).await {
             Ok(result) => {
                 if result.matches() {
                     HttpResponse::Ok().finish()
-                } else {
+                } else if result.messages().is_empty() {
                     HttpResponse::NotAcceptable().finish()
+                } else {
+                    HttpResponse::NotAcceptable().json(json!({
+                        "messages": result.messages(),
+                    }))
                 }
             }
             Err(err) => {